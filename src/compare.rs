@@ -0,0 +1,121 @@
+// Compares two images of the same type and size pixel by pixel, for regression testing programs
+// that render images. Besides a few numbers summing up how far apart they are, the
+// differences are drawn as a heatmap the viewer can show like any other image.
+
+use ppmviewer::{Image, ImageHeader, Pixel, PixelData, PpmType};
+
+/// Colors the heatmap runs through as the difference grows, evenly spaced from 0 to 255:
+/// identical pixels are black, small differences blue, then red, yellow and white.
+const HEATMAP_RAMP: [[u8; 3]; 5] = [[0, 0, 0], [0, 0, 255], [255, 0, 0], [255, 255, 0], [255, 255, 255]];
+
+/// How two images differ, see `compare`.
+pub struct Difference {
+    /// how many pixels differ in at least one channel
+    pub differing: usize,
+    pub pixel_count: usize,
+    /// the largest difference of any channel of any pixel
+    pub max: u8,
+    /// the difference of each pixel's most different channel, averaged over every pixel
+    pub mean: f64,
+    /// the difference of each pixel's most different channel, drawn with `HEATMAP_RAMP`
+    pub heatmap: Image,
+}
+
+/// Compares `a` and `b`, which must be the same type and size. Missing pixels of a short
+/// raster count as opaque black, and every channel including alpha is compared.
+pub fn compare(a: &Image, b: &Image) -> Result<Difference, String> {
+    if a.header.ppm_type != b.header.ppm_type {
+        return Err(format!("the images have different types, {} and {}", a.header.ppm_type, b.header.ppm_type));
+    }
+    let (width, height) = (a.header.width, a.header.height);
+    if (width, height) != (b.header.width, b.header.height) {
+        return Err(format!("the images have different sizes, {}x{} and {}x{}", width, height, b.header.width, b.header.height));
+    }
+
+    let mut differing = 0;
+    let mut max = 0;
+    let mut total = 0u64;
+    let mut values = Vec::with_capacity(a.header.pixel_count().unwrap_or(0));
+    for ((_, _, pixel_a), (_, _, pixel_b)) in a.enumerate_pixels().zip(b.enumerate_pixels()) {
+        let difference = [
+            pixel_a.r.abs_diff(pixel_b.r),
+            pixel_a.g.abs_diff(pixel_b.g),
+            pixel_a.b.abs_diff(pixel_b.b),
+            pixel_a.a.abs_diff(pixel_b.a),
+        ].iter().copied().max().unwrap_or(0);
+        if difference > 0 {
+            differing += 1;
+        }
+        max = max.max(difference);
+        total += u64::from(difference);
+        values.push(heatmap_color(difference));
+    }
+
+    let pixel_count = values.len();
+    Ok(Difference {
+        differing,
+        pixel_count,
+        max,
+        mean: if pixel_count == 0 { 0.0 } else { total as f64 / pixel_count as f64 },
+        heatmap: Image {
+            header: ImageHeader { ppm_type: PpmType::P6, width, height, max_value: 255, depth: 3 },
+            values: PixelData::Rgba(values),
+        },
+    })
+}
+
+/// Prints the summary of `difference`, e.g. `differing pixels: 12 of 100 (12.00%)`.
+pub fn print_difference(difference: &Difference) {
+    let percent = if difference.pixel_count == 0 { 0.0 } else { 100.0 * difference.differing as f64 / difference.pixel_count as f64 };
+    println!("differing pixels: {} of {} ({:.2}%)", difference.differing, difference.pixel_count, percent);
+    println!("max difference: {}", difference.max);
+    println!("mean difference: {:.2}", difference.mean);
+}
+
+/// The color of `difference` on the heatmap, blending between the two nearest colors of
+/// `HEATMAP_RAMP`.
+fn heatmap_color(difference: u8) -> Pixel {
+    let position = f32::from(difference) / 255.0 * (HEATMAP_RAMP.len() - 1) as f32;
+    let low = (position as usize).min(HEATMAP_RAMP.len() - 2);
+    let t = position - low as f32;
+    let channel = |i: usize| {
+        let (from, to) = (f32::from(HEATMAP_RAMP[low][i]), f32::from(HEATMAP_RAMP[low + 1][i]));
+        (from + (to - from) * t).round() as u8
+    };
+    Pixel::new(channel(0), channel(1), channel(2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ppmviewer::decode;
+
+    #[test]
+    fn summarizes_the_differences() {
+        let a = decode(&b"P3\n2 2\n255\n0 0 0  10 20 30  255 255 255  1 2 3\n"[..]).unwrap();
+        let b = decode(&b"P3\n2 2\n255\n0 0 0  10 20 30  0 255 255  1 2 9\n"[..]).unwrap();
+        let difference = compare(&a, &b).unwrap();
+        assert_eq!((difference.differing, difference.pixel_count, difference.max), (2, 4, 255));
+        assert_eq!(difference.mean, (255.0 + 6.0) / 4.0);
+        let heatmap: Vec<[u8; 4]> = difference.heatmap.values.iter().map(<[u8; 4]>::from).collect();
+        assert_eq!(heatmap, [[0, 0, 0, 255], [0, 0, 0, 255], [255, 255, 255, 255], [0, 0, 24, 255]]);
+    }
+
+    #[test]
+    fn rejects_different_sizes_and_types() {
+        let image = decode(&b"P3\n1 1\n255\n1 2 3\n"[..]).unwrap();
+        let wider = decode(&b"P3\n2 1\n255\n1 2 3 4 5 6\n"[..]).unwrap();
+        let binary = decode(&b"P6\n1 1\n255\n\x01\x02\x03"[..]).unwrap();
+        assert_eq!(compare(&image, &wider).err().unwrap(), "the images have different sizes, 1x1 and 2x1");
+        assert_eq!(compare(&image, &binary).err().unwrap(), "the images have different types, P3 and P6");
+    }
+
+    #[test]
+    fn heatmap_ramp() {
+        let color = |difference| <[u8; 4]>::from(heatmap_color(difference));
+        assert_eq!(color(0), [0, 0, 0, 255]);
+        assert_eq!(color(6), [0, 0, 24, 255]);
+        assert_eq!(color(255), [255, 255, 255, 255]);
+        assert_eq!(color(32), [0, 0, 128, 255]);
+    }
+}
@@ -0,0 +1,126 @@
+// Writes decoded images back out in the PPM family of formats. Every decoded image is
+// already scaled to 0..=255, so everything is written with a max value of 255.
+
+use crate::{Image, Pixel, PpmType};
+use std::io::{self, prelude::*};
+
+/// The spec asks for ASCII lines to be no longer than 70 characters
+const MAX_ASCII_LINE: usize = 70;
+
+/// Writes `image` as the given type. Only P2, P3, P5 and P6 can be written, anything
+/// else is an `InvalidInput` error.
+pub fn encode<W: Write>(writer: &mut W, image: &Image, ppm_type: &PpmType) -> io::Result<()> {
+    match ppm_type {
+        PpmType::P2 => write_p2(writer, image),
+        PpmType::P3 => write_p3(writer, image),
+        PpmType::P5 => write_p5(writer, image),
+        PpmType::P6 => write_p6(writer, image),
+        other => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("can't write {} files", other))),
+    }
+}
+
+/// Writes `image` as an ASCII graymap (P2).
+pub fn write_p2<W: Write>(writer: &mut W, image: &Image) -> io::Result<()> {
+    write_header(writer, "P2", image)?;
+    let samples: Vec<u8> = pixels(image).map(|pixel| luminance(&pixel)).collect();
+    write_ascii_samples(writer, &samples)
+}
+
+/// Writes `image` as an ASCII pixmap (P3).
+pub fn write_p3<W: Write>(writer: &mut W, image: &Image) -> io::Result<()> {
+    write_header(writer, "P3", image)?;
+    let samples: Vec<u8> = pixels(image).flat_map(|pixel| rgb(&pixel).to_vec()).collect();
+    write_ascii_samples(writer, &samples)
+}
+
+/// Writes `image` as a binary graymap (P5).
+pub fn write_p5<W: Write>(writer: &mut W, image: &Image) -> io::Result<()> {
+    write_header(writer, "P5", image)?;
+    let samples: Vec<u8> = pixels(image).map(|pixel| luminance(&pixel)).collect();
+    writer.write_all(&samples)
+}
+
+/// Writes `image` as a binary pixmap (P6).
+pub fn write_p6<W: Write>(writer: &mut W, image: &Image) -> io::Result<()> {
+    write_header(writer, "P6", image)?;
+    let samples: Vec<u8> = pixels(image).flat_map(|pixel| rgb(&pixel).to_vec()).collect();
+    writer.write_all(&samples)
+}
+
+/// Writes the magic number, dimensions and max value, each on their own line.
+fn write_header<W: Write>(writer: &mut W, magic: &str, image: &Image) -> io::Result<()> {
+    write!(writer, "{}\n{} {}\n255\n", magic, image.header.width.max(0), image.header.height.max(0))
+}
+
+/// Every pixel described by the header, with any missing from a short raster as black.
+fn pixels(image: &Image) -> impl Iterator<Item = Pixel> + '_ {
+    let pixel_count = image.header.pixel_count().unwrap_or(0);
+    image.values.iter()
+        .chain(std::iter::repeat(Pixel::new(0, 0, 0)))
+        .take(pixel_count)
+}
+
+fn rgb(pixel: &Pixel) -> [u8; 3] {
+    [pixel.r, pixel.g, pixel.b]
+}
+
+/// Converts a pixel to grayscale using the ITU-R BT.601 luma weights.
+fn luminance(pixel: &Pixel) -> u8 {
+    let [r, g, b] = rgb(pixel);
+    (0.299 * f32::from(r) + 0.587 * f32::from(g) + 0.114 * f32::from(b)).round() as u8
+}
+
+/// Writes samples as space separated numbers, wrapping lines before they get too long.
+fn write_ascii_samples<W: Write>(writer: &mut W, samples: &[u8]) -> io::Result<()> {
+    let mut line = String::new();
+    for sample in samples {
+        let text = sample.to_string();
+        if !line.is_empty() && line.len() + 1 + text.len() > MAX_ASCII_LINE {
+            writeln!(writer, "{}", line)?;
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&text);
+    }
+    if !line.is_empty() {
+        writeln!(writer, "{}", line)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode;
+
+    fn image() -> Image {
+        decode(&b"P3\n3 1\n255\n255 0 0  12 34 56  200 200 200\n"[..]).unwrap()
+    }
+
+    /// Writes `image()` with `write` and decodes it again, as RGBA arrays.
+    fn round_trip(write: fn(&mut Vec<u8>, &Image) -> io::Result<()>) -> Vec<[u8; 4]> {
+        let mut file = Vec::new();
+        write(&mut file, &image()).unwrap();
+        decode(&file[..]).unwrap().values.iter().map(<[u8; 4]>::from).collect()
+    }
+
+    #[test]
+    fn pixmaps_round_trip() {
+        let expected = [[255, 0, 0, 255], [12, 34, 56, 255], [200, 200, 200, 255]];
+        assert_eq!(round_trip(write_p3), expected);
+        assert_eq!(round_trip(write_p6), expected);
+    }
+
+    #[test]
+    fn graymaps_round_trip_as_luminance() {
+        let expected: Vec<[u8; 4]> = image().values.iter().map(|pixel| {
+            let gray = luminance(&pixel);
+            [gray, gray, gray, 255]
+        }).collect();
+        assert_eq!(expected[0], [76, 76, 76, 255]);
+        assert_eq!(round_trip(write_p2), expected);
+        assert_eq!(round_trip(write_p5), expected);
+    }
+}
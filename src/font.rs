@@ -0,0 +1,155 @@
+// A small bitmap font for the text the viewer draws itself, such as the hexdump of a file
+// that won't open and the status line.
+
+/// Size in pixels of a character cell, a 5x8 glyph with a column and a row of spacing
+pub const CELL_WIDTH: usize = 6;
+pub const CELL_HEIGHT: usize = 9;
+
+/// A 5x8 font for printable ASCII, from ' ' to '~'. Every glyph is 5 columns, with the
+/// lowest bit being the top row.
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5f, 0x00, 0x00], // '!'
+    [0x00, 0x07, 0x00, 0x07, 0x00], // '"'
+    [0x14, 0x7f, 0x14, 0x7f, 0x14], // '#'
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12], // '$'
+    [0x23, 0x13, 0x08, 0x64, 0x62], // '%'
+    [0x36, 0x49, 0x56, 0x20, 0x50], // '&'
+    [0x00, 0x08, 0x07, 0x03, 0x00], // "'"
+    [0x00, 0x1c, 0x22, 0x41, 0x00], // '('
+    [0x00, 0x41, 0x22, 0x1c, 0x00], // ')'
+    [0x2a, 0x1c, 0x7f, 0x1c, 0x2a], // '*'
+    [0x08, 0x08, 0x3e, 0x08, 0x08], // '+'
+    [0x00, 0x80, 0x70, 0x30, 0x00], // ','
+    [0x08, 0x08, 0x08, 0x08, 0x08], // '-'
+    [0x00, 0x00, 0x60, 0x60, 0x00], // '.'
+    [0x20, 0x10, 0x08, 0x04, 0x02], // '/'
+    [0x3e, 0x51, 0x49, 0x45, 0x3e], // '0'
+    [0x00, 0x42, 0x7f, 0x40, 0x00], // '1'
+    [0x72, 0x49, 0x49, 0x49, 0x46], // '2'
+    [0x21, 0x41, 0x49, 0x4d, 0x33], // '3'
+    [0x18, 0x14, 0x12, 0x7f, 0x10], // '4'
+    [0x27, 0x45, 0x45, 0x45, 0x39], // '5'
+    [0x3c, 0x4a, 0x49, 0x49, 0x31], // '6'
+    [0x41, 0x21, 0x11, 0x09, 0x07], // '7'
+    [0x36, 0x49, 0x49, 0x49, 0x36], // '8'
+    [0x46, 0x49, 0x49, 0x29, 0x1e], // '9'
+    [0x00, 0x00, 0x14, 0x00, 0x00], // ':'
+    [0x00, 0x40, 0x34, 0x00, 0x00], // ';'
+    [0x00, 0x08, 0x14, 0x22, 0x41], // '<'
+    [0x14, 0x14, 0x14, 0x14, 0x14], // '='
+    [0x00, 0x41, 0x22, 0x14, 0x08], // '>'
+    [0x02, 0x01, 0x59, 0x09, 0x06], // '?'
+    [0x3e, 0x41, 0x5d, 0x59, 0x4e], // '@'
+    [0x7c, 0x12, 0x11, 0x12, 0x7c], // 'A'
+    [0x7f, 0x49, 0x49, 0x49, 0x36], // 'B'
+    [0x3e, 0x41, 0x41, 0x41, 0x22], // 'C'
+    [0x7f, 0x41, 0x41, 0x41, 0x3e], // 'D'
+    [0x7f, 0x49, 0x49, 0x49, 0x41], // 'E'
+    [0x7f, 0x09, 0x09, 0x09, 0x01], // 'F'
+    [0x3e, 0x41, 0x41, 0x51, 0x73], // 'G'
+    [0x7f, 0x08, 0x08, 0x08, 0x7f], // 'H'
+    [0x00, 0x41, 0x7f, 0x41, 0x00], // 'I'
+    [0x20, 0x40, 0x41, 0x3f, 0x01], // 'J'
+    [0x7f, 0x08, 0x14, 0x22, 0x41], // 'K'
+    [0x7f, 0x40, 0x40, 0x40, 0x40], // 'L'
+    [0x7f, 0x02, 0x1c, 0x02, 0x7f], // 'M'
+    [0x7f, 0x04, 0x08, 0x10, 0x7f], // 'N'
+    [0x3e, 0x41, 0x41, 0x41, 0x3e], // 'O'
+    [0x7f, 0x09, 0x09, 0x09, 0x06], // 'P'
+    [0x3e, 0x41, 0x51, 0x21, 0x5e], // 'Q'
+    [0x7f, 0x09, 0x19, 0x29, 0x46], // 'R'
+    [0x26, 0x49, 0x49, 0x49, 0x32], // 'S'
+    [0x03, 0x01, 0x7f, 0x01, 0x03], // 'T'
+    [0x3f, 0x40, 0x40, 0x40, 0x3f], // 'U'
+    [0x1f, 0x20, 0x40, 0x20, 0x1f], // 'V'
+    [0x3f, 0x40, 0x38, 0x40, 0x3f], // 'W'
+    [0x63, 0x14, 0x08, 0x14, 0x63], // 'X'
+    [0x03, 0x04, 0x78, 0x04, 0x03], // 'Y'
+    [0x61, 0x59, 0x49, 0x4d, 0x43], // 'Z'
+    [0x00, 0x7f, 0x41, 0x41, 0x41], // '['
+    [0x02, 0x04, 0x08, 0x10, 0x20], // '\\'
+    [0x00, 0x41, 0x41, 0x41, 0x7f], // ']'
+    [0x04, 0x02, 0x01, 0x02, 0x04], // '^'
+    [0x40, 0x40, 0x40, 0x40, 0x40], // '_'
+    [0x00, 0x03, 0x07, 0x08, 0x00], // '`'
+    [0x20, 0x54, 0x54, 0x78, 0x40], // 'a'
+    [0x7f, 0x28, 0x44, 0x44, 0x38], // 'b'
+    [0x38, 0x44, 0x44, 0x44, 0x28], // 'c'
+    [0x38, 0x44, 0x44, 0x28, 0x7f], // 'd'
+    [0x38, 0x54, 0x54, 0x54, 0x18], // 'e'
+    [0x00, 0x08, 0x7e, 0x09, 0x02], // 'f'
+    [0x18, 0xa4, 0xa4, 0x9c, 0x78], // 'g'
+    [0x7f, 0x08, 0x04, 0x04, 0x78], // 'h'
+    [0x00, 0x44, 0x7d, 0x40, 0x00], // 'i'
+    [0x20, 0x40, 0x40, 0x3d, 0x00], // 'j'
+    [0x7f, 0x10, 0x28, 0x44, 0x00], // 'k'
+    [0x00, 0x41, 0x7f, 0x40, 0x00], // 'l'
+    [0x7c, 0x04, 0x78, 0x04, 0x78], // 'm'
+    [0x7c, 0x08, 0x04, 0x04, 0x78], // 'n'
+    [0x38, 0x44, 0x44, 0x44, 0x38], // 'o'
+    [0xfc, 0x18, 0x24, 0x24, 0x18], // 'p'
+    [0x18, 0x24, 0x24, 0x18, 0xfc], // 'q'
+    [0x7c, 0x08, 0x04, 0x04, 0x08], // 'r'
+    [0x48, 0x54, 0x54, 0x54, 0x24], // 's'
+    [0x04, 0x04, 0x3f, 0x44, 0x24], // 't'
+    [0x3c, 0x40, 0x40, 0x20, 0x7c], // 'u'
+    [0x1c, 0x20, 0x40, 0x20, 0x1c], // 'v'
+    [0x3c, 0x40, 0x30, 0x40, 0x3c], // 'w'
+    [0x44, 0x28, 0x10, 0x28, 0x44], // 'x'
+    [0x4c, 0x90, 0x90, 0x90, 0x7c], // 'y'
+    [0x44, 0x64, 0x54, 0x4c, 0x44], // 'z'
+    [0x00, 0x08, 0x36, 0x41, 0x00], // '{'
+    [0x00, 0x00, 0x77, 0x00, 0x00], // '|'
+    [0x00, 0x41, 0x36, 0x08, 0x00], // '}'
+    [0x02, 0x01, 0x02, 0x04, 0x02], // '~'
+];
+
+/// Calls `plot` with the position of every pixel of `text` that is set, relative to its
+/// top left corner. Characters outside printable ASCII are drawn as a space, or as `~`
+/// past it.
+pub fn plot_text(text: &str, mut plot: impl FnMut(usize, usize)) {
+    for (column, character) in text.bytes().enumerate() {
+        let glyph = FONT[usize::from(character.saturating_sub(b' ')).min(FONT.len() - 1)];
+        for (x, bits) in glyph.iter().enumerate() {
+            for y in (0..8).filter(|y| bits & (1 << y) != 0) {
+                plot(column * CELL_WIDTH + x, y);
+            }
+        }
+    }
+}
+
+/// Draws `text` in `color` into an RGBA framebuffer `width` pixels wide, with its top left
+/// corner at `x`, `y`. Only the pixels of the glyphs are touched, and anything past the
+/// right or bottom edge of the frame is cut off.
+pub fn draw_text(frame: &mut [u8], width: usize, x: usize, y: usize, text: &str, color: [u8; 4]) {
+    plot_text(text, |glyph_x, glyph_y| {
+        if x + glyph_x < width {
+            let start = ((y + glyph_y) * width + x + glyph_x) * 4;
+            if let Some(pixel) = frame.get_mut(start..start + 4) {
+                pixel.copy_from_slice(&color);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_a() {
+        let (width, height) = (8, 10);
+        let mut frame = vec![0; width * height * 4];
+        draw_text(&mut frame, width, 1, 1, "A", [0xff, 0, 0, 0xff]);
+        let on = |x: usize, y: usize| frame[(y * width + x) * 4..][..4] == [0xff, 0, 0, 0xff];
+        // the apex, both legs and the crossbar, relative to the origin at 1, 1
+        assert!(on(1 + 2, 1));
+        assert!(on(1, 1 + 2) && on(1, 1 + 6) && on(1 + 4, 1 + 2) && on(1 + 4, 1 + 6));
+        assert!((0..5).all(|x| on(1 + x, 1 + 4)));
+        // the corners beside the apex and the hole above the crossbar
+        assert!(!on(1, 1) && !on(1 + 4, 1) && !on(1 + 2, 1 + 2));
+        // nothing outside the glyph
+        assert_eq!(frame.iter().filter(|channel| **channel == 0xff).count() / 2, 16);
+    }
+}
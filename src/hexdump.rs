@@ -0,0 +1,46 @@
+// Renders the first bytes of a file that isn't a Netpbm image as a hexdump, drawn into an
+// image so the viewer can show it like any other. Meant for figuring out why a file
+// doesn't open: a wrong magic number, a byte order mark, text where binary was expected.
+
+use crate::font::{self, CELL_HEIGHT, CELL_WIDTH};
+use ppmviewer::{Image, ImageHeader, Pixel, PixelData, PpmType};
+
+/// How many bytes of the file are shown
+pub const HEXDUMP_BYTES: u64 = 256;
+
+/// Bytes shown per line
+const BYTES_PER_LINE: usize = 16;
+
+/// Empty space around the text, in pixels
+const PADDING: usize = 4;
+
+const TEXT_COLOR: Pixel = Pixel { r: 0xe0, g: 0xe0, b: 0xe0, a: 0xff };
+const BACKGROUND_COLOR: Pixel = Pixel { r: 0x20, g: 0x20, b: 0x20, a: 0xff };
+
+/// Draws `bytes` as lines like `00000010  50 33 0a 32 ...  |P3.2 2.255.....|`, the offset,
+/// the bytes in hex and the bytes as ASCII with a `.` for anything that isn't printable.
+pub fn hexdump_image(bytes: &[u8]) -> Image {
+    let lines: Vec<String> = bytes.chunks(BYTES_PER_LINE).enumerate().map(|(line, chunk)| {
+        let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let ascii: String = chunk.iter()
+            .map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { char::from(*byte) } else { '.' })
+            .collect();
+        format!("{:08x}  {:<width$}  |{}|", line * BYTES_PER_LINE, hex.join(" "), ascii, width = BYTES_PER_LINE * 3 - 1)
+    }).collect();
+    // an empty file still gets a line, so there's something to show
+    let lines = if lines.is_empty() { vec![String::from("(empty file)")] } else { lines };
+
+    let columns = lines.iter().map(String::len).max().unwrap_or(0);
+    let width = columns * CELL_WIDTH + 2 * PADDING;
+    let height = lines.len() * CELL_HEIGHT + 2 * PADDING;
+    let mut values = vec![BACKGROUND_COLOR; width * height];
+    for (row, line) in lines.iter().enumerate() {
+        let top = PADDING + row * CELL_HEIGHT;
+        font::plot_text(line, |x, y| values[(top + y) * width + PADDING + x] = TEXT_COLOR);
+    }
+
+    Image {
+        header: ImageHeader { ppm_type: PpmType::P6, width: width as i32, height: height as i32, max_value: 255, depth: 3 },
+        values: PixelData::Rgba(values),
+    }
+}
@@ -0,0 +1,1523 @@
+// Format data is based on the structure/explaination found at: http://paulbourke.net/dataformats/ppm/
+
+#![deny(clippy::all)]
+// mapping a file into memory is inherently unsafe, so the mmap feature allows it in that one place
+#![cfg_attr(not(feature = "mmap"), forbid(unsafe_code))]
+#![cfg_attr(feature = "mmap", deny(unsafe_code))]
+
+use std::io::{self, prelude::*, BufReader};
+use std::convert::TryFrom;
+use std::fmt;
+
+pub mod encoder;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+
+/// First two bytes of a gzip stream
+#[cfg(feature = "gzip")]
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The UTF-8 byte order mark some editors put at the start of a file
+const UTF8_BOM: [u8; 3] = [0xef, 0xbb, 0xbf];
+
+/// Upper limit on how many pixels are reserved before decoding starts
+const MAX_PREALLOCATED_PIXELS: usize = 1 << 24;
+
+/// The information stored in the header of a PPM/PGM/PBM file
+#[derive(Debug, Clone)]
+pub struct ImageHeader {
+  pub ppm_type: PpmType,
+  pub width: i32,
+  pub height: i32,
+  /// The largest sample value. Bitmaps (P1/P4) don't store one, their header has it set
+  /// to 1 since every sample is either 0 or 1.
+  pub max_value: i32,
+  /// Number of samples per pixel. Only PAM (P7) files give this explicitly, for the
+  /// other formats it follows from the type (1 for P1/P2/P4/P5, 3 for P3/P6).
+  pub depth: i32,
+}
+
+/// A decoded image, with every pixel already scaled to 0..=255
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub header: ImageHeader,
+    pub values: PixelData,
+}
+
+/// The pixels of an image. Grayscale images keep a single sample per pixel instead of
+/// a whole `Pixel`, a quarter of the memory, but read the same either way: `get`, `iter`
+/// and the accessors of `Image` always return full pixels.
+#[derive(Debug, Clone)]
+pub enum PixelData {
+    /// one sample per pixel, for opaque grayscale images
+    Gray(Vec<u8>),
+    Rgba(Vec<Pixel>),
+}
+
+impl PixelData {
+    /// Empty storage suited to the image `header` describes, with some space reserved.
+    pub fn for_header(header: &ImageHeader) -> Self {
+        if header.depth == 1 {
+            PixelData::Gray(Vec::with_capacity(header.pixel_capacity()))
+        } else {
+            PixelData::Rgba(Vec::with_capacity(header.pixel_capacity()))
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            PixelData::Gray(samples) => samples.len(),
+            PixelData::Rgba(pixels) => pixels.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The pixel at `index`, counting row by row from the top left.
+    pub fn get(&self, index: usize) -> Option<Pixel> {
+        match self {
+            PixelData::Gray(samples) => samples.get(index).map(|gray| Pixel::new(*gray, *gray, *gray)),
+            PixelData::Rgba(pixels) => pixels.get(index).copied(),
+        }
+    }
+
+    /// Every pixel, row by row from the top left.
+    pub fn iter(&self) -> impl Iterator<Item = Pixel> + '_ {
+        let (gray, rgba) = match self {
+            PixelData::Gray(samples) => (Some(samples.iter()), None),
+            PixelData::Rgba(pixels) => (None, Some(pixels.iter())),
+        };
+        let gray = gray.into_iter().flatten().map(|gray| Pixel::new(*gray, *gray, *gray));
+        gray.chain(rgba.into_iter().flatten().copied())
+    }
+
+    /// Adds a pixel at the end. A pixel that isn't opaque gray turns grayscale storage into
+    /// full pixels first.
+    pub fn push(&mut self, pixel: Pixel) {
+        match self {
+            PixelData::Gray(samples) if pixel.r == pixel.g && pixel.g == pixel.b && pixel.a == 255 => samples.push(pixel.r),
+            PixelData::Gray(_) => {
+                *self = PixelData::Rgba(self.iter().collect());
+                self.push(pixel);
+            }
+            PixelData::Rgba(pixels) => pixels.push(pixel),
+        }
+    }
+
+    /// Pads the pixels out to `len` with copies of `pixel`.
+    pub fn resize(&mut self, len: usize, pixel: Pixel) {
+        while self.len() < len {
+            self.push(pixel);
+        }
+    }
+}
+
+impl Default for PixelData {
+    fn default() -> Self {
+        PixelData::Rgba(Vec::new())
+    }
+}
+
+impl Extend<Pixel> for PixelData {
+    fn extend<I: IntoIterator<Item = Pixel>>(&mut self, pixels: I) {
+        pixels.into_iter().for_each(|pixel| self.push(pixel));
+    }
+}
+
+/// A single pixel of an image
+#[derive(Debug, Clone, Copy)]
+pub struct Pixel {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    /// opacity, 255 is fully opaque
+    pub a: u8
+}
+
+impl Pixel {
+    /// A fully opaque pixel.
+    pub fn new(red: u8, green: u8, blue: u8) -> Self {
+        Self::rgba(red, green, blue, 255)
+    }
+
+    pub fn rgba(red: u8, green: u8, blue: u8, alpha: u8) -> Self {
+        Pixel {
+            r: red,
+            g: green,
+            b: blue,
+            a: alpha
+        }
+    }
+}
+
+/// A fully opaque pixel from its red, green and blue channels.
+impl From<[u8; 3]> for Pixel {
+    fn from([red, green, blue]: [u8; 3]) -> Self {
+        Self::new(red, green, blue)
+    }
+}
+
+/// A fully opaque pixel from its red, green and blue channels.
+impl From<(u8, u8, u8)> for Pixel {
+    fn from((red, green, blue): (u8, u8, u8)) -> Self {
+        Self::new(red, green, blue)
+    }
+}
+
+/// The channels of a pixel in RGBA order, as the framebuffer takes them.
+impl From<Pixel> for [u8; 4] {
+    fn from(pixel: Pixel) -> Self {
+        [pixel.r, pixel.g, pixel.b, pixel.a]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Determines the format type of file based on the the first two bytes
+/// of the Magic Number
+pub enum PpmType {
+    /// P1 is the Bitmap Data in ASCII
+    P1,
+    /// P2 is the Grayscale Data in ASCII
+    P2,
+    /// P3 is the RGB Image data in ASCII
+    P3,
+    /// P4 is the Bitmap Data in Binary Format
+    P4,
+    /// P5 is the Grayscale Data in Binary Format
+    P5,
+    /// P6 is the RGB Image Data in Binary Format
+    P6,
+    /// P7 is the PAM format, binary data with an arbitrary number of channels
+    P7,
+    /// This is not a valid PPM/PGM/PBM File Format 
+    P0,
+}
+
+impl PpmType {
+    /// The type named by the two magic bytes a file starts with, e.g. `*b"P6"`. Anything
+    /// that isn't the magic number of a Netpbm format is `P0`.
+    pub fn from_magic(bytes: [u8; 2]) -> PpmType {
+        match bytes {
+            [80, 49] => { PpmType::P1 },
+            [80, 50] => { PpmType::P2 },
+            [80, 51] => { PpmType::P3 },
+            [80, 52] => { PpmType::P4 },
+            [80, 53] => { PpmType::P5 },
+            [80, 54] => { PpmType::P6 },
+            [80, 55] => { PpmType::P7 },
+            _ => PpmType::P0,
+        }
+    }
+
+    /// Whether the raster is written as ASCII numbers (P1, P2 and P3).
+    pub fn is_ascii(&self) -> bool {
+        matches!(self, PpmType::P1 | PpmType::P2 | PpmType::P3)
+    }
+
+    /// Whether the raster is written as raw bytes (P4, P5, P6 and P7).
+    pub fn is_binary(&self) -> bool {
+        matches!(self, PpmType::P4 | PpmType::P5 | PpmType::P6 | PpmType::P7)
+    }
+
+    /// Whether the header stores a max value. Bitmaps (P1 and P4) don't.
+    pub fn has_max_value(&self) -> bool {
+        !matches!(self, PpmType::P1 | PpmType::P4)
+    }
+}
+
+impl fmt::Display for PpmType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// How closely a file has to follow the Netpbm spec to be decoded.
+///
+/// Every level accepts what the spec allows, including any amount of whitespace and
+/// comments between the numbers of the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strictness {
+    /// Rejects the deviations `Normal` lets through: a raster holding fewer pixels than the
+    /// header promises, ASCII samples that are negative or larger than the max value,
+    /// anything before the magic number, and anything but the next magic number right after
+    /// the raster of an image in a stream of several.
+    Strict,
+    /// Rejects broken headers, but shows what it can of the raster: missing pixels are left
+    /// out, samples out of range are clamped, and a UTF-8 byte order mark and whitespace
+    /// before the magic number, as some editors write, and whitespace between the images
+    /// of a stream are skipped.
+    #[default]
+    Normal,
+    /// Everything `Normal` accepts, and also:
+    /// - a missing max value, taken to be 255, when the height is followed by the end of
+    ///   the file or by a byte that can't start a number. For ASCII images a missing max
+    ///   value can't be told apart from the first sample, so it isn't detected there.
+    /// - a max value followed directly by the raster, without the whitespace byte between
+    ///   them, as long as the raster doesn't start with a digit.
+    /// - a CR LF line break after the last number of the header, which is taken as the
+    ///   single whitespace byte ending it rather than the LF being part of the raster.
+    /// - an error partway through the raster, which stops decoding instead of failing.
+    ///   The pixels decoded so far are kept and the rest are transparent black.
+    Lenient,
+}
+
+impl ImageHeader {
+  pub fn new() -> Self {
+    ImageHeader {
+      ppm_type: PpmType::P0,
+      width: 0,
+      height: 0,
+      max_value: 0,
+      depth: 0,
+    }
+  }
+
+  /// Number of pixels described by the header, `width * height`. Returns None when
+  /// either dimension is negative or the product doesn't fit in a usize.
+  pub fn pixel_count(&self) -> Option<usize> {
+    let width = usize::try_from(self.width).ok()?;
+    let height = usize::try_from(self.height).ok()?;
+    width.checked_mul(height)
+  }
+
+  /// Checks that the header describes an image that can actually be decoded: both
+  /// dimensions positive, a pixel count that doesn't overflow, and (except for bitmaps,
+  /// which have none) a max value between 1 and 65535.
+  pub fn check(&self) -> Result<(), PpmError> {
+    if self.ppm_type == PpmType::P0 {
+      return Err(PpmError::InvalidHeader(String::from("unknown image type")));
+    }
+    if self.width <= 0 || self.height <= 0 {
+      return Err(PpmError::InvalidHeader(format!("dimensions must be positive, got {}x{}", self.width, self.height)));
+    }
+    if self.width.checked_mul(self.height).is_none() || self.pixel_count().is_none() {
+      return Err(PpmError::InvalidHeader(format!("{}x{} is too many pixels", self.width, self.height)));
+    }
+    if self.ppm_type.has_max_value() && (self.max_value <= 0 || self.max_value > 65535) {
+      return Err(PpmError::InvalidHeader(format!("max value must be between 1 and 65535, got {}", self.max_value)));
+    }
+    Ok(())
+  }
+
+  /// Number of bytes the raster of a binary (P4/P5/P6/P7) image takes up. Returns None for
+  /// the ASCII types, and when the size doesn't fit in a usize.
+  pub fn raster_size(&self) -> Option<usize> {
+    let width = usize::try_from(self.width).ok()?;
+    let height = usize::try_from(self.height).ok()?;
+    let sample_size = if self.max_value > 255 { 2 } else { 1 };
+    match self.ppm_type {
+      // every row of a bitmap is padded out to a whole byte
+      PpmType::P4 => width.div_ceil(8).checked_mul(height),
+      PpmType::P5 | PpmType::P6 | PpmType::P7 => {
+        let depth = usize::try_from(self.depth).ok()?;
+        self.pixel_count()?.checked_mul(depth)?.checked_mul(sample_size)
+      }
+      _ => None,
+    }
+  }
+
+  /// How many pixels to reserve space for up front. This is capped, since the header
+  /// alone can claim an image far larger than the file actually holds.
+  fn pixel_capacity(&self) -> usize {
+    self.pixel_count().unwrap_or(0).min(MAX_PREALLOCATED_PIXELS)
+  }
+}
+
+impl Default for ImageHeader {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Errors that can come up while reading a PPM/PGM/PBM file
+#[derive(Debug)]
+pub enum PpmError {
+    /// The file could not be opened or read
+    Io(io::Error),
+    /// The file ended before the magic number could be read
+    TooShort,
+    /// The first two bytes aren't the magic number of any Netpbm format
+    NotNetpbm([u8; 2]),
+    /// The header is malformed or describes something that can't be decoded
+    InvalidHeader(String),
+    /// A token in an ASCII raster isn't a valid sample, `pixel` is the index of the pixel it belongs to
+    InvalidSample { token: String, pixel: usize },
+}
+
+impl fmt::Display for PpmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PpmError::Io(e) => write!(f, "{}", e),
+            PpmError::TooShort => write!(f, "file is too short to be a PPM file"),
+            PpmError::NotNetpbm([first, second]) => write!(f, "not a Netpbm file: unexpected magic bytes {:#04X} {:#04X}", first, second),
+            PpmError::InvalidHeader(reason) => write!(f, "invalid header: {}", reason),
+            PpmError::InvalidSample { token, pixel } => write!(f, "invalid sample '{}' near pixel {}", token, pixel),
+        }
+    }
+}
+
+impl std::error::Error for PpmError {}
+
+impl From<io::Error> for PpmError {
+    fn from(e: io::Error) -> Self {
+        PpmError::Io(e)
+    }
+}
+
+impl Image {
+    pub fn new() -> Self {
+        Image {
+            header: ImageHeader::new(),
+            values: PixelData::default(),
+        }
+    }
+
+    /// Builds a `width * height * 4` byte framebuffer in RGBA order, with each pixel's own alpha.
+    /// Any pixels missing from a short raster come out as opaque black.
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        let pixel_count = self.header.pixel_count().unwrap_or(0);
+        let mut rgba = Vec::with_capacity(pixel_count * 4);
+        for pixel in self.values.iter().take(pixel_count) {
+            rgba.extend_from_slice(&<[u8; 4]>::from(pixel));
+        }
+        while rgba.len() < pixel_count * 4 {
+            rgba.extend_from_slice(&[0, 0, 0, 0xff]);
+        }
+        rgba
+    }
+
+    /// The pixel at column `x` of row `y`. Returns None outside the image, and for pixels
+    /// missing from a short raster.
+    pub fn pixel(&self, x: u32, y: u32) -> Option<Pixel> {
+        let width = u32::try_from(self.header.width).ok()?;
+        let height = u32::try_from(self.header.height).ok()?;
+        if x >= width || y >= height {
+            return None;
+        }
+        self.values.get(y as usize * width as usize + x as usize)
+    }
+
+    /// Every pixel along with its column and row, row by row from the top left, matching
+    /// `pixel(x, y)`. Yields exactly `width * height` pixels, with any missing from a short
+    /// raster as opaque black.
+    pub fn enumerate_pixels(&self) -> impl Iterator<Item = (u32, u32, Pixel)> + '_ {
+        let width = self.header.width.max(1) as usize;
+        let pixel_count = self.header.pixel_count().unwrap_or(0);
+        self.values.iter()
+            .chain(std::iter::repeat(Pixel::new(0, 0, 0)))
+            .take(pixel_count)
+            .enumerate()
+            .map(move |(index, pixel)| ((index % width) as u32, (index / width) as u32, pixel))
+    }
+}
+
+impl Default for Image {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Hands the decoded pixels over to the `image` crate, alpha included. Pixels missing from
+/// a short raster come out as opaque black, like they do in `to_rgba8`.
+#[cfg(feature = "image")]
+impl From<Image> for image::RgbaImage {
+    fn from(ppm: Image) -> Self {
+        let width = ppm.header.width.max(0) as u32;
+        let height = ppm.header.height.max(0) as u32;
+        image::ImageBuffer::from_raw(width, height, ppm.to_rgba8())
+            .expect("to_rgba8 returns exactly width * height pixels")
+    }
+}
+
+/// Hands the decoded pixels over to the `image` crate, dropping the alpha channel.
+#[cfg(feature = "image")]
+impl From<Image> for image::RgbImage {
+    fn from(ppm: Image) -> Self {
+        let width = ppm.header.width.max(0) as u32;
+        let height = ppm.header.height.max(0) as u32;
+        let rgb = ppm.to_rgba8().chunks_exact(4).flat_map(|rgba| rgba[..3].to_vec()).collect();
+        image::ImageBuffer::from_raw(width, height, rgb)
+            .expect("to_rgba8 returns exactly width * height pixels")
+    }
+}
+
+/// Reads only the header of a PPM/PGM/PBM/PAM image, returning it along with its length
+/// in bytes. The raster isn't touched, so the cost depends on the size of the header
+/// rather than the image, and this is quick even for very large images. A reader that
+/// can seek can go straight to the raster from there, at the returned length.
+///
+/// The reader is buffered, so more than the header may be read from it. See
+/// `read_header_consumed` for readers that can't seek back.
+pub fn read_header(reader: impl Read) -> Result<(usize, ImageHeader), PpmError> {
+    read_header_with(reader, Strictness::Normal)
+}
+
+/// Like `read_header`, parsing the header as strictly as `strictness` asks for.
+pub fn read_header_with(reader: impl Read, strictness: Strictness) -> Result<(usize, ImageHeader), PpmError> {
+    read_ppm_header(&mut open_stream(reader)?, strictness)
+}
+
+/// Like `read_header`, for readers that can't seek, such as stdin. Along with the header it
+/// returns every byte it read from `reader`, which may be more than the header. Chained in
+/// front of what is left of the reader they make up the whole stream again, so the image
+/// can still be decoded after looking at its header, e.g. with
+/// `decode(io::Cursor::new(consumed).chain(reader))`. Pass `&mut reader` to keep using it.
+pub fn read_header_consumed(reader: impl Read) -> Result<(Vec<u8>, ImageHeader), PpmError> {
+    let mut recording = RecordingReader { inner: reader, recorded: Vec::new() };
+    let (_, header) = read_header(&mut recording)?;
+    Ok((recording.recorded, header))
+}
+
+/// Keeps a copy of everything read through it, for `read_header_consumed`.
+struct RecordingReader<R> {
+    inner: R,
+    recorded: Vec<u8>,
+}
+
+impl<R: Read> Read for RecordingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.recorded.extend_from_slice(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// Decodes a PPM/PGM/PBM/PAM image from any reader. The image is read in a single forward
+/// pass, so the reader doesn't need to support seeking (e.g. stdin). Only the first image
+/// is decoded from a stream of several, see `decode_all` for the rest.
+pub fn decode(reader: impl Read) -> Result<Image, PpmError> {
+    decode_with(reader, Strictness::Normal)
+}
+
+/// Like `decode`, but for images that are only partly readable, e.g. a file that is
+/// still being written. Once the header has been read, an error partway through the
+/// raster stops decoding instead of failing: the pixels decoded so far are returned, and
+/// the rest of the image is padded with transparent black pixels. Errors in the header
+/// are still returned, as without it there is nothing to show. This is `decode_with`
+/// using `Strictness::Lenient`, which also tolerates a few common header mistakes.
+pub fn decode_lenient(reader: impl Read) -> Result<Image, PpmError> {
+    decode_with(reader, Strictness::Lenient)
+}
+
+/// Like `decode`, following the spec as closely as `strictness` asks for.
+pub fn decode_with(reader: impl Read, strictness: Strictness) -> Result<Image, PpmError> {
+    decode_frame(&mut open_stream(reader)?, strictness).map(|(image, _)| image)
+}
+
+/// Decodes every image from a stream of images written one after the other, as rendering
+/// pipelines often do for animations. The images can be binary or ASCII, or a mix of both.
+pub fn decode_all(reader: impl Read) -> Result<Vec<Image>, PpmError> {
+    decode_all_with(reader, Strictness::Normal)
+}
+
+/// Like `decode_all`, but lenient about errors in the raster the way `decode_lenient` is.
+/// The stream can't be followed past an image that failed partway, so that image is the
+/// last one returned.
+pub fn decode_all_lenient(reader: impl Read) -> Result<Vec<Image>, PpmError> {
+    decode_all_with(reader, Strictness::Lenient)
+}
+
+/// Like `decode_all`, following the spec as closely as `strictness` asks for.
+pub fn decode_all_with(reader: impl Read, strictness: Strictness) -> Result<Vec<Image>, PpmError> {
+    decode_frames(&mut open_stream(reader)?, strictness)
+}
+
+fn decode_frames<R: BufRead>(reader: &mut R, strictness: Strictness) -> Result<Vec<Image>, PpmError> {
+    let (image, mut complete) = decode_frame(reader, strictness)?;
+    let mut images = vec![image];
+    while complete {
+        // tolerate whitespace between images unless strict, then stop once there's nothing left
+        loop {
+            let buffer = reader.fill_buf()?;
+            let whitespace = buffer.iter().take_while(|byte| is_header_whitespace(**byte)).count();
+            if whitespace == 0 || strictness == Strictness::Strict {
+                break;
+            }
+            reader.consume(whitespace);
+        }
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            break;
+        }
+        if !starts_image(buffer) {
+            let trailing = io::copy(reader, &mut io::sink())?;
+            if strictness == Strictness::Strict {
+                return Err(PpmError::InvalidHeader(format!("{} trailing bytes after the last image", trailing)));
+            }
+            warn_trailing(trailing);
+            break;
+        }
+        let (image, image_complete) = decode_frame(reader, strictness)?;
+        images.push(image);
+        complete = image_complete;
+    }
+    Ok(images)
+}
+
+/// Whether `bytes`, whatever follows the raster of an image, could be the start of another
+/// image. Anything else is junk appended to the file.
+fn starts_image(bytes: &[u8]) -> bool {
+    match bytes {
+        [b'P', digit, ..] => PpmType::from_magic([b'P', *digit]) != PpmType::P0,
+        // the rest of the magic number may not be buffered yet
+        [b'P'] => true,
+        _ => false,
+    }
+}
+
+/// Logs a warning about bytes past the end of the last image, which are ignored.
+fn warn_trailing(bytes: u64) {
+    if bytes > 0 {
+        log::warn!("ignored {} trailing bytes", bytes);
+    }
+}
+
+/// Buffers the reader, and with the gzip feature transparently decompresses it when it
+/// starts with the gzip magic bytes, so e.g. `.ppm.gz` files decode like any other.
+fn open_stream<'a>(reader: impl Read + 'a) -> Result<Box<dyn BufRead + 'a>, PpmError> {
+    #[allow(unused_mut)]
+    let mut reader = BufReader::new(reader);
+    #[cfg(feature = "gzip")]
+    {
+        if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+            // several gzip members one after the other decompress as one stream, like gunzip does
+            return Ok(Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader))));
+        }
+    }
+    Ok(Box::new(reader))
+}
+
+/// Decodes a single image, leaving the reader just past the end of its raster. When
+/// lenient, an error in the raster keeps what was decoded and pads out the rest. Also
+/// returns whether the raster was read without errors.
+fn decode_frame<R: BufRead>(reader: &mut R, strictness: Strictness) -> Result<(Image, bool), PpmError> {
+    let mut image = Image::new();
+    let (_, header) = read_ppm_header(reader, strictness)?;
+    image.header = header.to_owned();
+    image.values = PixelData::for_header(&header);
+
+    let mut raster = if header.ppm_type.is_ascii() {
+        read_ppm_ascii_file(reader, &header, strictness, &mut image.values).map(|_| ())
+    } else if header.ppm_type.is_binary() {
+        read_ppm_binary_image_data(reader, &header, &mut image.values)
+    } else {
+        Ok(())
+    };
+    let pixel_count = header.pixel_count().unwrap_or(0);
+    if raster.is_ok() && strictness == Strictness::Strict && image.values.len() < pixel_count {
+        let message = format!("raster ended after {} of {} pixels", image.values.len(), pixel_count);
+        raster = Err(PpmError::Io(io::Error::new(io::ErrorKind::UnexpectedEof, message)));
+    }
+    match raster {
+        Err(_) if strictness == Strictness::Lenient => {
+            if image.values.len() < pixel_count {
+                image.values.resize(pixel_count, Pixel::rgba(0, 0, 0, 0));
+            }
+            Ok((image, false))
+        }
+        Err(e) => Err(e),
+        Ok(()) => Ok((image, true)),
+    }
+}
+
+/// Reads the raster of an ASCII (P1/P2/P3) file. The header has already been parsed by
+/// `read_ppm_header`, which leaves the reader positioned at the start of the raster.
+///
+/// The raster is read as one flat stream of whitespace separated samples, so line breaks
+/// mean nothing: a line may hold any number of pixels, or only part of one, and may be as
+/// long as it likes. A `#` still starts a comment that runs to the end of its line.
+///
+/// Pixels are appended to `values` as they are decoded, so on an error it holds every
+/// pixel before it. Reading stops at the first sample past the last pixel, after skipping
+/// any whitespace and comments up to it, so whatever follows (e.g. the next image of an
+/// animation) is left in the reader. Returns how many bytes of the raster were read.
+fn read_ppm_ascii_file<R: BufRead>(reader: &mut R, header: &ImageHeader, strictness: Strictness, values: &mut PixelData) -> Result<usize, PpmError> {
+    let samples_per_pixel = if header.ppm_type == PpmType::P3 { 3 } else { 1 };
+    let mut samples: Vec<u8> = Vec::with_capacity(samples_per_pixel);
+    let mut clamped = 0;
+    let pixel_count = header.pixel_count().unwrap_or(0);
+    let mut trailing = 0;
+
+    let mut push_token = |token: &[u8], values: &mut PixelData| -> Result<(), PpmError> {
+        if header.ppm_type == PpmType::P1 {
+            // the bits don't need whitespace between them, "0110" is four pixels just like "0 1 1 0"
+            for (i, bit) in token.iter().enumerate() {
+                if values.len() >= pixel_count {
+                    trailing += token.len() - i;
+                    break;
+                }
+                // like in P4, a set bit is black
+                let pixel_data = match bit {
+                    b'0' => 255,
+                    b'1' => 0,
+                    _ => return Err(PpmError::InvalidSample { token: char::from(*bit).to_string(), pixel: values.len() }),
+                };
+                values.push(Pixel::new(pixel_data, pixel_data, pixel_data));
+            }
+            return Ok(());
+        }
+
+        let token = String::from_utf8_lossy(token);
+        let sample = parse_number(&token)
+            .filter(|sample| strictness != Strictness::Strict || (0..=header.max_value).contains(sample))
+            .ok_or_else(|| PpmError::InvalidSample { token: token.to_string(), pixel: values.len() })?;
+        if sample > header.max_value {
+            clamped += 1;
+        }
+        samples.push(scale_sample(sample, header.max_value));
+        if samples.len() == samples_per_pixel {
+            values.push(match samples[..] {
+                [r, g, b] => Pixel::new(r, g, b),
+                _ => Pixel::new(samples[0], samples[0], samples[0]),
+            });
+            samples.clear();
+        }
+        Ok(())
+    };
+
+    let mut token = Vec::new();
+    let mut in_comment = false;
+    let mut consumed = 0;
+    while let Some(&byte) = reader.fill_buf()?.first() {
+        if values.len() >= pixel_count && token.is_empty() && !in_comment && byte != b'#' && !is_header_whitespace(byte) {
+            break;
+        }
+        reader.consume(1);
+        consumed += 1;
+        if in_comment {
+            in_comment = byte != b'\n' && byte != b'\r';
+            continue;
+        }
+        if byte == b'#' || is_header_whitespace(byte) {
+            in_comment = byte == b'#';
+            if !token.is_empty() {
+                push_token(&token, values)?;
+                token.clear();
+            }
+        } else {
+            token.push(byte);
+        }
+    }
+    if !token.is_empty() {
+        push_token(&token, values)?;
+    }
+    warn_clamped(clamped, header.max_value);
+    warn_trailing(trailing as u64);
+    Ok(consumed)
+}
+
+/// Given a reader, it will parse the header information for the PPM family of files
+/// and returns the byte position where the header ends as well as the data inside
+/// the header object. Nothing past the header is consumed, so the raster can be read
+/// straight from the same reader afterwards.
+///
+/// Per the spec the header ends with exactly one whitespace byte after the last number
+/// (the max value, or the height for P1/P4), and the raster starts right after it. Any
+/// of space, tab, LF, VT, FF or CR ends the header, so in a header ending in CR LF the
+/// LF is already the first byte of the raster.
+fn read_ppm_header<R: BufRead>(f: &mut R, strictness: Strictness) -> Result<(usize, ImageHeader), PpmError> {
+    let mut byte_position: usize = 0;
+
+    let mut header: ImageHeader = ImageHeader::new();
+    let mut magic_number = [0; 2];
+
+    /*#region Get the type of PPM file */
+    if strictness != Strictness::Strict {
+        byte_position += skip_before_magic(f)?;
+    }
+
+    // Get the type of PPM file we are reading
+    // anything shorter than the two magic bytes can't be a PPM file
+    if let Err(e) = f.read_exact(&mut magic_number) {
+        return Err(match e.kind() {
+            io::ErrorKind::UnexpectedEof => PpmError::TooShort,
+            _ => PpmError::Io(e),
+        });
+    }
+    header.ppm_type = match PpmType::from_magic(magic_number) {
+        PpmType::P0 => return Err(PpmError::NotNetpbm(magic_number)),
+        ppm_type => ppm_type,
+    };
+    byte_position += 2;
+    /* #endregion */
+
+    // PAM has a completely different, line based header
+    if header.ppm_type == PpmType::P7 {
+        let (byte_position, header) = read_pam_header(f, byte_position, header)?;
+        header.check()?;
+        return Ok((byte_position, header));
+    }
+    header.depth = match header.ppm_type {
+        PpmType::P3 | PpmType::P6 => 3,
+        _ => 1,
+    };
+
+    // P1 and P4 have no max value, every other type has three numbers in the header
+    let field_count = if header.ppm_type.has_max_value() { 3 } else { 2 };
+    let lenient = strictness == Strictness::Lenient;
+    let mut tokenizer = HeaderTokenizer::new(f, byte_position);
+    for (field, name) in ["width", "height", "max value"].iter().enumerate().take(field_count) {
+        if lenient && field == 2 && !tokenizer.peek()?.is_some_and(starts_number) {
+            // the height is followed straight by the raster, so the max value is missing
+            header.max_value = 255;
+            break;
+        }
+        let token = tokenizer.next_token(name, lenient && field + 1 == field_count)?;
+        let token = String::from_utf8_lossy(&token);
+        let value = parse_number(&token).ok_or_else(|| {
+            PpmError::InvalidHeader(format!("expected a number but found {:?}", token))
+        })?;
+        match field {
+            0 => header.width = value,
+            1 => header.height = value,
+            _ => header.max_value = value,
+        }
+    }
+    if !header.ppm_type.has_max_value() {
+        header.max_value = 1;
+    }
+
+    header.check()?;
+    Ok((tokenizer.bytes_read, header))
+}
+
+/// Whether `byte` can begin the next number of a header, possibly after whitespace or a
+/// comment.
+fn starts_number(byte: u8) -> bool {
+    byte.is_ascii_digit() || byte == b'+' || byte == b'-' || byte == b'#' || is_header_whitespace(byte)
+}
+
+/// Parses a single number out of a header or an ASCII raster. Stray carriage returns
+/// from CRLF line endings are ignored.
+fn parse_number(token: &str) -> Option<i32> {
+    token.trim_matches('\r').parse::<i32>().ok()
+}
+
+/// Whitespace as defined by the Netpbm spec: space, tab, LF, VT, FF and CR.
+fn is_header_whitespace(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\n' | 0x0b | 0x0c | b'\r')
+}
+
+/// Splits a PPM/PGM/PBM header into its numbers, skipping the whitespace and comments
+/// around them, and counts every byte it reads. It reads a byte at a time and never
+/// further than the end of the number it returns, so the reader can be handed on to
+/// the raster afterwards.
+struct HeaderTokenizer<'a, R> {
+    reader: &'a mut R,
+    /// bytes read so far, including any read before the tokenizer was created
+    bytes_read: usize,
+}
+
+impl<'a, R: BufRead> HeaderTokenizer<'a, R> {
+    fn new(reader: &'a mut R, bytes_read: usize) -> Self {
+        HeaderTokenizer { reader, bytes_read }
+    }
+
+    /// The next byte, without consuming it. None at the end of the file.
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.reader.fill_buf()?.first().copied())
+    }
+
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = self.peek()?;
+        if byte.is_some() {
+            self.reader.consume(1);
+            self.bytes_read += 1;
+        }
+        Ok(byte)
+    }
+
+    /// Reads the next number, skipping any whitespace and comments in front of it. The
+    /// single whitespace byte that ends the number is consumed as well and nothing after
+    /// it, so after the last number of the header the reader sits exactly at the start of
+    /// the raster.
+    ///
+    /// A file that ends before the number is complete is an `UnexpectedEof` error naming the
+    /// header `field` that was being read, e.g. "max value".
+    ///
+    /// `lenient_end` is set for the last number of a lenient header. The number then also
+    /// ends where the digits do, at the end of the file or a raster that follows without any
+    /// whitespace, and a CR LF after it counts as a single line break.
+    fn next_token(&mut self, field: &str, lenient_end: bool) -> Result<Vec<u8>, PpmError> {
+        let mut token = Vec::new();
+        let mut in_comment = false;
+        loop {
+            if lenient_end && !token.is_empty() && !in_comment && self.peek()?.is_none_or(|byte| !starts_number(byte)) {
+                return Ok(token);
+            }
+            let byte = match self.next_byte()? {
+                Some(byte) => byte,
+                None => {
+                    let message = format!("file ended before the {} of the header", field);
+                    return Err(PpmError::Io(io::Error::new(io::ErrorKind::UnexpectedEof, message)));
+                }
+            };
+
+            if in_comment {
+                // a comment runs until the end of the line
+                in_comment = byte != b'\n' && byte != b'\r';
+            } else if byte == b'#' {
+                in_comment = true;
+                if !token.is_empty() {
+                    // the comment ends the number, skip the rest of it before returning
+                    while let Some(byte) = self.next_byte()? {
+                        if byte == b'\n' || byte == b'\r' {
+                            break;
+                        }
+                    }
+                    return Ok(token);
+                }
+            } else if is_header_whitespace(byte) {
+                if !token.is_empty() {
+                    if lenient_end && byte == b'\r' && self.peek()? == Some(b'\n') {
+                        self.next_byte()?;
+                    }
+                    return Ok(token);
+                }
+            } else {
+                token.push(byte);
+            }
+        }
+    }
+}
+
+/// Skips the UTF-8 byte order mark and whitespace editors sometimes leave before the
+/// magic number. Returns how many bytes were skipped.
+fn skip_before_magic<R: BufRead>(reader: &mut R) -> io::Result<usize> {
+    let mut skipped = 0;
+    if reader.fill_buf()?.starts_with(&UTF8_BOM) {
+        reader.consume(UTF8_BOM.len());
+        skipped += UTF8_BOM.len();
+    }
+    loop {
+        let whitespace = reader.fill_buf()?.iter().take_while(|byte| is_header_whitespace(**byte)).count();
+        if whitespace == 0 {
+            return Ok(skipped);
+        }
+        reader.consume(whitespace);
+        skipped += whitespace;
+    }
+}
+
+/// Parses the rest of a PAM (P7) header, everything after the magic number up to and
+/// including the ENDHDR line. Each line holds a keyword followed by its value, e.g.
+/// `WIDTH 640`, and lines starting with '#' are comments.
+fn read_pam_header<R: Read>(f: &mut R, mut byte_position: usize, mut header: ImageHeader) -> Result<(usize, ImageHeader), PpmError> {
+    let mut tuple_type: Option<String> = None;
+    let mut found_end = false;
+
+    let mut line = Vec::new();
+    let mut byte_for = [0; 1];
+    while f.read(&mut byte_for)? != 0 {
+        byte_position += 1;
+        if byte_for[0] != 10 {
+            line.push(byte_for[0]);
+            continue
+        }
+
+        let text = String::from_utf8_lossy(&line).trim().to_string();
+        line.clear();
+        if text.is_empty() || text.starts_with('#') {
+            continue
+        }
+        let (keyword, value) = match text.find(char::is_whitespace) {
+            Some(split) => (&text[..split], text[split..].trim()),
+            None => (text.as_str(), ""),
+        };
+        let parse_value = || parse_number(value).ok_or_else(|| PpmError::InvalidHeader(format!("{} is not a number: {:?}", keyword, value)));
+        match keyword {
+            "WIDTH" => header.width = parse_value()?,
+            "HEIGHT" => header.height = parse_value()?,
+            "DEPTH" => header.depth = parse_value()?,
+            "MAXVAL" => header.max_value = parse_value()?,
+            // the tuple type may be split over several lines, which are joined with a space
+            "TUPLTYPE" => tuple_type = Some(match tuple_type {
+                Some(previous) => format!("{} {}", previous, value),
+                None => value.to_string(),
+            }),
+            "ENDHDR" => {
+                found_end = true;
+                break
+            }
+            _ => return Err(PpmError::InvalidHeader(format!("unknown PAM header line {:?}", text))),
+        }
+    }
+
+    if !found_end {
+        return Err(PpmError::InvalidHeader(String::from("PAM header is missing ENDHDR")));
+    }
+    // the tuple type is optional, but when it is given it has to agree with the depth
+    let expected_depth = match tuple_type.as_deref() {
+        None => None,
+        Some("GRAYSCALE") => Some(1),
+        Some("RGB") => Some(3),
+        Some("RGB_ALPHA") => Some(4),
+        Some(other) => return Err(PpmError::InvalidHeader(format!("unsupported PAM tuple type {}", other))),
+    };
+    match expected_depth {
+        Some(expected) if expected != header.depth => {
+            return Err(PpmError::InvalidHeader(format!(
+                "DEPTH {} does not match TUPLTYPE {}",
+                header.depth,
+                tuple_type.unwrap_or_default()
+            )));
+        }
+        None if header.depth != 1 && header.depth != 3 && header.depth != 4 => {
+            return Err(PpmError::InvalidHeader(format!("unsupported PAM depth {}", header.depth)));
+        }
+        _ => {}
+    }
+
+    Ok((byte_position, header))
+}
+
+/// Reads the raster of a binary (P4/P5/P6/P7) file. The reader must already be positioned
+/// just past the header.
+fn read_ppm_binary_image_data<R: Read>(f: &mut R, header: &ImageHeader, values: &mut PixelData) -> Result<(), PpmError> {
+
+    // read the whole raster in one go, then slice it up into pixels in memory. Only the
+    // raster's own bytes are read, since another image may follow it in the stream.
+    let mut raster = Vec::new();
+    let read = match header.raster_size() {
+        Some(size) => f.take(size as u64).read_to_end(&mut raster),
+        None => f.read_to_end(&mut raster),
+    };
+    // whatever was read before an error is still turned into pixels
+    let pixels = pixels_from_raster(&raster, header);
+    if values.is_empty() {
+        *values = pixels;
+    } else {
+        values.extend(pixels.iter());
+    }
+    read?;
+    Ok(())
+}
+
+/// Slices the raster of a binary (P4/P5/P6/P7) image up into pixels.
+fn pixels_from_raster(raster: &[u8], header: &ImageHeader) -> PixelData {
+    let mut img_data = PixelData::for_header(header);
+    for_each_raster_pixel(raster, header, |pixel| img_data.push(pixel));
+    img_data
+}
+
+/// Calls `emit` with every pixel of the raster of a binary (P4/P5/P6/P7) image, row by row
+/// from the top left. A partial pixel at the end of the raster is left out.
+fn for_each_raster_pixel(raster: &[u8], header: &ImageHeader, mut emit: impl FnMut(Pixel)) {
+    let max_value = header.max_value;
+
+    if header.ppm_type == PpmType::P6 && max_value > 255 {
+        // 16 bit samples are stored as two bytes per channel, most significant byte first
+        for byte_for in raster.chunks_exact(6) {
+            emit(Pixel::new(
+              scale_sample(i32::from_be_bytes([0,0,byte_for[0],byte_for[1]]), max_value),
+              scale_sample(i32::from_be_bytes([0,0,byte_for[2],byte_for[3]]), max_value),
+              scale_sample(i32::from_be_bytes([0,0,byte_for[4],byte_for[5]]), max_value)
+            ));
+        }
+    } else if header.ppm_type == PpmType::P6 {
+        for byte_for in raster.chunks_exact(3) {
+            emit(Pixel::new(
+              scale_sample(i32::from(byte_for[0]), max_value),
+              scale_sample(i32::from(byte_for[1]), max_value),
+              scale_sample(i32::from(byte_for[2]), max_value)
+            ));
+        }
+    } else if header.ppm_type == PpmType::P5 && max_value > 255 {
+        for byte_for in raster.chunks_exact(2) {
+            let gs_data = scale_sample(i32::from_be_bytes([0,0,byte_for[0],byte_for[1]]), max_value);
+            emit(Pixel::new(gs_data, gs_data, gs_data));
+        }
+    } else if header.ppm_type == PpmType::P5 {
+        for byte_for in raster.iter() {
+            let gs_data = scale_sample(i32::from(*byte_for), max_value);
+            emit(Pixel::new(gs_data, gs_data, gs_data));
+        }
+    } else if header.ppm_type == PpmType::P7 {
+        // samples are one byte each, or two bytes (most significant first) past 255
+        let sample_size = if max_value > 255 { 2 } else { 1 };
+        let depth = header.depth.max(1) as usize;
+        for tuple in raster.chunks_exact(depth * sample_size) {
+            let samples: Vec<u8> = tuple.chunks_exact(sample_size).map(|sample| {
+                let value = if sample_size == 2 {
+                    i32::from_be_bytes([0, 0, sample[0], sample[1]])
+                } else {
+                    i32::from(sample[0])
+                };
+                scale_sample(value, max_value)
+            }).collect();
+            // grayscale tuples have a single sample, a fourth sample is alpha
+            if depth < 3 {
+                emit(Pixel::new(samples[0], samples[0], samples[0]));
+            } else if depth == 4 {
+                emit(Pixel::rgba(samples[0], samples[1], samples[2], samples[3]));
+            } else {
+                emit(Pixel::new(samples[0], samples[1], samples[2]));
+            }
+        }
+    } else if header.ppm_type == PpmType::P4 {
+        let width = header.width;
+        let mut column = 0;
+        for byte_for in raster.iter() {
+            for i in (0..8).rev() {
+                // every row is padded out to a whole byte, the remaining bits are not pixels
+                if column == width {
+                    break;
+                }
+                column += 1;
+
+                let pixel_data = get_bit_at(*byte_for, i).unwrap();
+
+                let final_value = if pixel_data {
+                    0
+                } else {
+                    255
+                };
+
+                emit(Pixel::new(
+                  final_value, 
+                  final_value, 
+                  final_value)
+                );
+            }
+            if column == width {
+                column = 0;
+            }
+        }
+    }
+
+    warn_clamped(count_over_max_value(raster, header), max_value);
+}
+
+/// Counts the samples of a binary P5/P6/P7 raster that are larger than the max value. Only
+/// possible when the max value doesn't use up the whole range of a sample.
+fn count_over_max_value(raster: &[u8], header: &ImageHeader) -> usize {
+    let max_value = header.max_value;
+    if !matches!(header.ppm_type, PpmType::P5 | PpmType::P6 | PpmType::P7) || max_value == 255 || max_value >= 65535 {
+        return 0;
+    }
+    if max_value > 255 {
+        raster.chunks_exact(2).filter(|sample| i32::from_be_bytes([0, 0, sample[0], sample[1]]) > max_value).count()
+    } else {
+        raster.iter().filter(|sample| i32::from(**sample) > max_value).count()
+    }
+}
+
+/// Logs a warning when samples were larger than the max value. They're clamped to it,
+/// but it means whatever wrote the file has a bug.
+fn warn_clamped(clamped: usize, max_value: i32) {
+    if clamped > 0 {
+        log::warn!("{} samples exceeded max_value {} and were clamped", clamped, max_value);
+    }
+}
+
+/// Scales a sample from the range 0..=max_value down to 0..=255 for display. Samples
+/// larger than max_value are clamped to 255, and a max_value of 0 (which `check` rejects
+/// for every type that has one) scales everything to 0 rather than dividing by zero.
+fn scale_sample(value: i32, max_value: i32) -> u8 {
+    if max_value <= 0 {
+        return 0;
+    }
+    ((value as f32 / max_value as f32) * 255.0).clamp(0.0, 255.0) as u8
+}
+
+fn get_bit_at(input: u8, n: u8) -> Result<bool, ()> {
+    if n < 8 {
+        Ok(input & (1 << n) != 0)
+    } else {
+        Err(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The pixels of `image` as RGBA arrays, which unlike `Pixel` can be compared.
+    fn rgba(image: &Image) -> Vec<[u8; 4]> {
+        image.values.iter().map(<[u8; 4]>::from).collect()
+    }
+
+    #[test]
+    fn sixteen_bit_graymap() {
+        let mut file = b"P5\n2 2\n65535\n".to_vec();
+        file.extend_from_slice(&[0x00, 0x00, 0xff, 0xff, 0x80, 0x00, 0x00, 0xff]);
+        let image = decode(&file[..]).unwrap();
+        assert_eq!(image.header.max_value, 65535);
+        assert_eq!(rgba(&image), [[0, 0, 0, 255], [255, 255, 255, 255], [127, 127, 127, 255], [0, 0, 0, 255]]);
+    }
+
+    #[test]
+    fn samples_are_scaled_by_max_value() {
+        let low = decode(&b"P3\n2 1\n15\n15 0 5 10 15 0\n"[..]).unwrap();
+        let full = decode(&b"P3\n2 1\n255\n255 0 85 170 255 0\n"[..]).unwrap();
+        assert_eq!(rgba(&low), rgba(&full));
+
+        let low = decode(&b"P6\n2 1\n15\n\x0f\x00\x05\x0a\x0f\x00"[..]).unwrap();
+        let full = decode(&b"P6\n2 1\n255\n\xff\x00\x55\xaa\xff\x00"[..]).unwrap();
+        assert_eq!(rgba(&low), rgba(&full));
+    }
+
+    #[test]
+    fn to_rgba8_layout() {
+        let image = decode(&b"P3\n2 2\n255\n1 2 3  4 5 6\n7 8 9  10 11 12\n"[..]).unwrap();
+        assert_eq!(image.to_rgba8(), [1, 2, 3, 255, 4, 5, 6, 255, 7, 8, 9, 255, 10, 11, 12, 255]);
+    }
+
+    #[test]
+    fn ascii_pixels_ignore_line_breaks() {
+        let one_per_line = decode(&b"P3\n3 1\n255\n10 20 30\n40 50 60\n70 80 90\n"[..]).unwrap();
+        let single_line = decode(&b"P3\n3 1\n255\n10 20 30 40 50 60 70 80 90\n"[..]).unwrap();
+        let split = decode(&b"P3\n3 1\n255\n10 20 30 40\n50 60 70 80\n90\n"[..]).unwrap();
+        assert_eq!(rgba(&single_line), rgba(&one_per_line));
+        assert_eq!(rgba(&split), rgba(&one_per_line));
+    }
+
+    #[test]
+    fn bitmap_rows_skip_padding_bits() {
+        // the rows are 10110 and 01001, the first padded with set bits to show they're skipped
+        let image = decode(&b"P4\n5 2\n\xb7\x48"[..]).unwrap();
+        let gray: Vec<u8> = image.values.iter().map(|pixel| pixel.r).collect();
+        assert_eq!(gray, [0, 255, 0, 0, 255, 255, 0, 255, 255, 0]);
+    }
+
+    #[test]
+    fn large_binary_pixmap() {
+        let (width, height) = (1024, 1024);
+        let color = |index: usize| [(index % 251) as u8, (index / 1024) as u8, (index % 7 * 36) as u8];
+        let mut file = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+        file.extend((0..width * height).flat_map(color));
+        let image = decode(&file[..]).unwrap();
+        assert_eq!(image.values.len(), width * height);
+        assert!(image.values.iter().enumerate().all(|(index, pixel)| [pixel.r, pixel.g, pixel.b] == color(index)));
+    }
+
+    #[test]
+    fn comment_between_magic_number_and_dimensions() {
+        let image = decode(&b"P2\n# made by hand\n3 1\n255\n0 128 255\n"[..]).unwrap();
+        assert_eq!((image.header.width, image.header.height, image.header.max_value), (3, 1, 255));
+        assert_eq!(rgba(&image), [[0, 0, 0, 255], [128, 128, 128, 255], [255, 255, 255, 255]]);
+    }
+
+    #[test]
+    fn header_ends_after_one_whitespace_byte() {
+        // the header is always "P6 1 1 255" plus the bytes ending it
+        for (ending, length) in [(&b"\n"[..], 11), (b" ", 11), (b"\r\n", 11)] {
+            let mut file = b"P6 1 1 255".to_vec();
+            file.extend_from_slice(ending);
+            file.extend_from_slice(&[1, 2, 3]);
+            let (header_length, header) = read_header(&file[..]).unwrap();
+            assert_eq!(header_length, length, "header ending in {:?}", ending);
+            assert_eq!(header.max_value, 255);
+        }
+    }
+
+    #[test]
+    fn ascii_raster_with_crlf_line_endings() {
+        let image = decode(&b"P3\r\n# windows\r\n2 1\r\n255\r\n1 2 3\r\n4 5 6\r\n"[..]).unwrap();
+        assert_eq!((image.header.width, image.header.height), (2, 1));
+        assert_eq!(rgba(&image), [[1, 2, 3, 255], [4, 5, 6, 255]]);
+    }
+
+    #[test]
+    fn ascii_bitmap_bits_run_together() {
+        let spaced = decode(&b"P1\n3 2\n0 1 0\n1 1 0\n"[..]).unwrap();
+        let packed = decode(&b"P1\n3 2\n010110\n"[..]).unwrap();
+        let gray: Vec<u8> = packed.values.iter().map(|pixel| pixel.r).collect();
+        assert_eq!(gray, [255, 0, 255, 0, 0, 255]);
+        assert_eq!(rgba(&packed), rgba(&spaced));
+        // the same bitmap as a P4, rows padded to a byte
+        let binary = decode(&b"P4\n3 2\n\x40\xc0"[..]).unwrap();
+        assert_eq!(rgba(&packed), rgba(&binary));
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn converts_into_image_buffers() {
+        let image = decode(&b"P3\n2 1\n255\n1 2 3 4 5 6\n"[..]).unwrap();
+        let rgba = image::RgbaImage::from(image.clone());
+        assert_eq!(rgba.dimensions(), (2, 1));
+        assert_eq!(rgba.get_pixel(1, 0).0, [4, 5, 6, 255]);
+        let rgb = image::RgbImage::from(image);
+        assert_eq!(rgb.get_pixel(0, 0).0, [1, 2, 3]);
+    }
+
+    #[test]
+    fn raster_starts_right_after_any_whitespace_byte() {
+        // a raster that itself starts with whitespace bytes, none of which may be skipped
+        let raster = [0x0a, 0x20, 0x09, 0x41, 0x42, 0x43];
+        for whitespace in [b' ', b'\t', b'\n', 0x0b, 0x0c, b'\r'] {
+            let mut file = b"P6 2 1 255".to_vec();
+            file.push(whitespace);
+            file.extend_from_slice(&raster);
+            let image = decode(&file[..]).unwrap();
+            assert_eq!(rgba(&image), [[0x0a, 0x20, 0x09, 0xff], [0x41, 0x42, 0x43, 0xff]], "max value followed by {:#04x}", whitespace);
+        }
+    }
+
+    #[test]
+    fn tab_between_width_and_height() {
+        let image = decode(&b"P2\n3\t2\n255\n0 1 2 3 4 5\n"[..]).unwrap();
+        assert_eq!((image.header.width, image.header.height), (3, 2));
+        assert_eq!(image.values.len(), 6);
+    }
+
+    #[test]
+    fn header_truncated_after_width() {
+        match decode(&b"P2\n3 "[..]) {
+            Err(PpmError::Io(e)) => {
+                assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+                assert!(e.to_string().contains("height"), "{}", e);
+            }
+            other => panic!("expected an UnexpectedEof error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decode_matches_header_plus_raster() {
+        let mut file = b"P6\n# two by two\n2 2\n255\n".to_vec();
+        file.extend(0..12);
+        let image = decode(&file[..]).unwrap();
+
+        let (header_length, header) = read_header(&file[..]).unwrap();
+        let raster = &file[header_length..header_length + header.raster_size().unwrap()];
+        assert_eq!(image.to_rgba8(), raster.chunks_exact(3).flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255]).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn comment_at_start_of_raster_line() {
+        let image = decode(&b"P2\n3 1\n255\n# first row\n10 20 30\n"[..]).unwrap();
+        assert_eq!(image.values.iter().map(|pixel| pixel.r).collect::<Vec<_>>(), [10, 20, 30]);
+    }
+
+    #[test]
+    fn comment_in_middle_of_raster_line() {
+        let image = decode(&b"P2\n3 1\n255\n10 20#30\n40\n"[..]).unwrap();
+        assert_eq!(image.values.iter().map(|pixel| pixel.r).collect::<Vec<_>>(), [10, 20, 40]);
+    }
+
+    #[test]
+    fn comment_at_end_of_raster_line() {
+        let image = decode(&b"P2\n3 1\n255\n10 20 30 #\n"[..]).unwrap();
+        assert_eq!(image.values.iter().map(|pixel| pixel.r).collect::<Vec<_>>(), [10, 20, 30]);
+    }
+
+    #[test]
+    fn lenient_missing_max_value() {
+        // the height is followed straight by the raster, whose first byte can't start a number
+        let file = b"P6\n2 1\n\xff\x00\x00\x00\xff\x00";
+        assert!(decode(&file[..]).is_err());
+        let image = decode_with(&file[..], Strictness::Lenient).unwrap();
+        assert_eq!(image.header.max_value, 255);
+        assert_eq!(rgba(&image), [[255, 0, 0, 255], [0, 255, 0, 255]]);
+
+        // or by the end of the file
+        let (_, header) = read_header_with(&b"P5\n2 1\n"[..], Strictness::Lenient).unwrap();
+        assert_eq!(header.max_value, 255);
+    }
+
+    #[test]
+    fn lenient_raster_right_after_max_value() {
+        let file = b"P6 1 1 255\xff\x80\x00";
+        assert!(decode(&file[..]).is_err());
+        let image = decode_with(&file[..], Strictness::Lenient).unwrap();
+        assert_eq!(rgba(&image), [[255, 128, 0, 255]]);
+    }
+
+    #[test]
+    fn lenient_keeps_pixels_before_an_error() {
+        let file = b"P3\n3 1\n255\n1 2 3 4 5 x\n";
+        assert!(decode(&file[..]).is_err());
+        let image = decode_lenient(&file[..]).unwrap();
+        assert_eq!(rgba(&image), [[1, 2, 3, 255], [0, 0, 0, 0], [0, 0, 0, 0]]);
+    }
+
+    #[test]
+    fn strict_rejects_what_normal_tolerates() {
+        // a short raster and an ASCII sample above the max value
+        for file in [&b"P5\n2 1\n255\n\x01"[..], b"P2\n1 1\n100\n101\n"] {
+            assert!(decode(file).is_ok());
+            assert!(decode_with(file, Strictness::Strict).is_err());
+        }
+    }
+
+    #[test]
+    fn bitmap_max_value_is_one() {
+        let (_, header) = read_header(&b"P4\n8 1\n\x0f"[..]).unwrap();
+        assert_eq!(header.max_value, 1);
+        let (_, header) = read_header(&b"P1\n2 1\n0 1\n"[..]).unwrap();
+        assert_eq!(header.max_value, 1);
+    }
+
+    #[test]
+    fn samples_above_max_value_are_clamped() {
+        let image = decode(&b"P3\n2 1\n255\n300 0 128  0 256 255\n"[..]).unwrap();
+        assert_eq!(rgba(&image), [[255, 0, 128, 255], [0, 255, 255, 255]]);
+        let image = decode(&b"P5\n2 1\n100\n\x64\xc8"[..]).unwrap();
+        assert_eq!(rgba(&image), [[255, 255, 255, 255], [255, 255, 255, 255]]);
+    }
+
+    #[test]
+    fn pixel_at_corners() {
+        let image = decode(&b"P2\n3 2\n255\n0 1 2\n3 4 5\n"[..]).unwrap();
+        let gray = |x, y| image.pixel(x, y).map(|pixel| pixel.r);
+        assert_eq!(gray(0, 0), Some(0));
+        assert_eq!(gray(2, 0), Some(2));
+        assert_eq!(gray(0, 1), Some(3));
+        assert_eq!(gray(2, 1), Some(5));
+    }
+
+    #[test]
+    fn pixel_out_of_range() {
+        let image = decode(&b"P2\n3 2\n255\n0 1 2\n3 4\n"[..]).unwrap();
+        assert!(image.pixel(3, 0).is_none());
+        assert!(image.pixel(0, 2).is_none());
+        assert!(image.pixel(u32::MAX, u32::MAX).is_none());
+        // inside the image, but missing from the short raster
+        assert!(image.pixel(2, 1).is_none());
+    }
+
+    #[test]
+    fn first_pixel_after_header_ending_in_lf_or_space() {
+        for ending in [&b"\n"[..], b" "] {
+            let mut file = b"P6\n2 1\n255".to_vec();
+            file.extend_from_slice(ending);
+            file.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+            let image = decode(&file[..]).unwrap();
+            assert_eq!(rgba(&image), [[1, 2, 3, 255], [4, 5, 6, 255]], "header ending in {:?}", ending);
+        }
+    }
+
+    #[test]
+    fn first_pixel_after_header_ending_in_crlf() {
+        let file = b"P6\n2 1\n255\r\n\x01\x02\x03\x04\x05\x06";
+        // per the spec the CR ends the header, so the LF is the first byte of the raster
+        let image = decode(&file[..]).unwrap();
+        assert_eq!(rgba(&image), [[0x0a, 1, 2, 255], [3, 4, 5, 255]]);
+        // lenient takes the CR LF as a single line break
+        let image = decode_lenient(&file[..]).unwrap();
+        assert_eq!(rgba(&image), [[1, 2, 3, 255], [4, 5, 6, 255]]);
+    }
+
+    #[test]
+    fn over_length_raster() {
+        let file = b"P6\n1 1\n255\n\x01\x02\x03\x04\x05\x06\n";
+        let images = decode_all(&file[..]).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(rgba(&images[0]), [[1, 2, 3, 255]]);
+        match decode_all_with(&file[..], Strictness::Strict) {
+            Err(PpmError::InvalidHeader(reason)) => assert_eq!(reason, "4 trailing bytes after the last image"),
+            other => panic!("expected the trailing bytes to be rejected, got {:?}", other),
+        }
+        // a line break after the raster is trailing data too as far as the spec goes
+        assert!(decode_all_with(&b"P6\n1 1\n255\n\x01\x02\x03\n"[..], Strictness::Strict).is_err());
+    }
+
+    #[test]
+    fn under_length_raster() {
+        let file = b"P6\n2 1\n255\n\x01\x02\x03\x04";
+        let images = decode_all(&file[..]).unwrap();
+        assert_eq!(rgba(&images[0]), [[1, 2, 3, 255]]);
+        match decode_all_with(&file[..], Strictness::Strict) {
+            Err(PpmError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            other => panic!("expected the short raster to be rejected, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn max_value_on_the_line_of_the_dimensions() {
+        let image = decode(&b"P2\n2 1 100\n50 100"[..]).unwrap();
+        assert_eq!((image.header.width, image.header.height, image.header.max_value), (2, 1, 100));
+        assert_eq!(rgba(&image), [[127, 127, 127, 255], [255, 255, 255, 255]]);
+
+        let image = decode(&b"P3 2 1 255 1 2 3 4 5 6"[..]).unwrap();
+        assert_eq!((image.header.width, image.header.height, image.header.max_value), (2, 1, 255));
+        assert_eq!(rgba(&image), [[1, 2, 3, 255], [4, 5, 6, 255]]);
+    }
+
+    #[test]
+    fn read_header_of_every_type() {
+        let files: [(&[u8], PpmType, i32); 7] = [
+            (b"P1\n3 2\n", PpmType::P1, 1),
+            (b"P2\n3 2\n15\n", PpmType::P2, 15),
+            (b"P3\n3 2\n255\n", PpmType::P3, 255),
+            (b"P4\n3 2\n", PpmType::P4, 1),
+            (b"P5\n3 2\n65535\n", PpmType::P5, 65535),
+            (b"P6\n3 2\n255\n", PpmType::P6, 255),
+            (b"P7\nWIDTH 3\nHEIGHT 2\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n", PpmType::P7, 255),
+        ];
+        for (file, ppm_type, max_value) in files.iter() {
+            let (length, header) = read_header(*file).unwrap();
+            assert_eq!(length, file.len(), "{}", ppm_type);
+            assert_eq!((&header.ppm_type, header.width, header.height, header.max_value), (ppm_type, 3, 2, *max_value));
+        }
+        assert!(matches!(read_header(&b"P8\n3 2\n"[..]), Err(PpmError::NotNetpbm([b'P', b'8']))));
+    }
+
+    #[test]
+    fn read_header_consumed_chains_into_decode() {
+        let file = b"P3\n2 1\n255\n1 2 3 4 5 6\n";
+        let mut reader = &file[..];
+        let (consumed, header) = read_header_consumed(&mut reader).unwrap();
+        assert_eq!((header.width, header.height), (2, 1));
+        let image = decode(io::Cursor::new(consumed).chain(reader)).unwrap();
+        assert_eq!(rgba(&image), [[1, 2, 3, 255], [4, 5, 6, 255]]);
+    }
+
+    #[test]
+    fn enumerate_pixels_of_3x2() {
+        let image = decode(&b"P2\n3 2\n255\n0 1 2\n3 4 5\n"[..]).unwrap();
+        let pixels: Vec<(u32, u32, u8)> = image.enumerate_pixels().map(|(x, y, pixel)| (x, y, pixel.r)).collect();
+        assert_eq!(pixels.len(), 6);
+        assert_eq!(pixels[0], (0, 0, 0));
+        assert_eq!(pixels[3], (0, 1, 3));
+        assert_eq!(pixels.last(), Some(&(2, 1, 5)));
+        assert!(image.enumerate_pixels().all(|(x, y, pixel)| image.pixel(x, y).map(|at| at.r) == Some(pixel.r)));
+    }
+
+    #[test]
+    fn raster_ending_mid_pixel() {
+        // two whole pixels and two bytes of a third
+        let file = b"P6\n3 1\n255\n\x01\x02\x03\x04\x05\x06\x07\x08";
+        let image = decode(&file[..]).unwrap();
+        assert_eq!(rgba(&image), [[1, 2, 3, 255], [4, 5, 6, 255]]);
+        match decode_with(&file[..], Strictness::Strict) {
+            Err(PpmError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            other => panic!("expected the partial pixel to be rejected, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn concatenated_ascii_frames() {
+        let file = b"P3\n2 1\n255\n1 2 3 4 5 6 # end\nP3\n1 1\n255\n7 8 9\n";
+        let images = decode_all(&file[..]).unwrap();
+        assert_eq!(images.len(), 2);
+        assert_eq!(rgba(&images[0]), [[1, 2, 3, 255], [4, 5, 6, 255]]);
+        assert_eq!(rgba(&images[1]), [[7, 8, 9, 255]]);
+
+        // the first raster, with the comment and line break after it, is 18 bytes long
+        let mut reader = &file[..];
+        let (header_length, header) = read_ppm_header(&mut reader, Strictness::Normal).unwrap();
+        let mut values = PixelData::for_header(&header);
+        let consumed = read_ppm_ascii_file(&mut reader, &header, Strictness::Normal, &mut values).unwrap();
+        assert_eq!((header_length, consumed), (11, 18));
+        assert!(reader.starts_with(b"P3\n1 1"));
+    }
+
+    #[test]
+    fn comments_are_skipped_whatever_they_hold() {
+        let image = decode(&b"P3\n# P6 was here # 12 34\n2 1 # 7 8 #\n255\n1 2 3 4 5 6\n"[..]).unwrap();
+        assert_eq!((&image.header.ppm_type, image.header.width, image.header.height, image.header.max_value), (&PpmType::P3, 2, 1, 255));
+        assert_eq!(rgba(&image), [[1, 2, 3, 255], [4, 5, 6, 255]]);
+    }
+
+    #[test]
+    fn byte_order_mark_before_the_magic_number() {
+        let file = b"\xef\xbb\xbfP3\n1 1\n255\n1 2 3\n";
+        let image = decode(&file[..]).unwrap();
+        assert_eq!(rgba(&image), [[1, 2, 3, 255]]);
+        assert!(decode_with(&file[..], Strictness::Strict).is_err());
+    }
+}
@@ -1,429 +1,475 @@
-// Format data is based on the structure/explaination found at: http://paulbourke.net/dataformats/ppm/
-
 #![deny(clippy::all)]
 #![forbid(unsafe_code)]
 
-extern crate args;
+mod compare;
+mod font;
+mod hexdump;
 
-use log::error;
-use pixels::{Error, Pixels, SurfaceTexture};
+use log::{debug, error, info, warn};
+use pixels::{wgpu, Error, Pixels, PixelsBuilder, SurfaceTexture};
+use ppmviewer::{decode, decode_all_with, encoder, read_header_with, Image, ImageHeader, PpmError, PpmType, Strictness};
 use winit::dpi::LogicalSize;
 use winit::event::{Event, VirtualKeyCode};
 use winit::event_loop::{ControlFlow, EventLoop};
-use winit::window::WindowBuilder;
+use winit::window::{Window, WindowBuilder};
 use winit_input_helper::WinitInputHelper;
-use std::fs::File;
-use std::io::{prelude::*, BufReader, SeekFrom};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 use std::env;
+use std::time::{Duration, Instant, SystemTime};
 
-/// Representation of the application state
-#[derive(Clone)]
-struct World {
-    frame: Option<PPM>,
-    single_draw: bool,
-    has_been_drawn: bool,
-}
+/// Smallest and largest zoom factor allowed on top of the fit-to-window scaling
+const MIN_ZOOM: f32 = 0.1;
+const MAX_ZOOM: f32 = 32.0;
 
-impl World {
-    fn get_width(self) -> i32 {
-        self.frame.unwrap().header.width
-    }
+/// How much a single notch of the mouse wheel zooms in or out by. Zoom factors above 1
+/// are rounded to whole numbers, so this has to be large enough to get from 1 to 2.
+const WHEEL_ZOOM_FACTOR: f32 = 1.5;
 
-    fn get_height(self) -> i32 {
-        self.frame.unwrap().header.height
-    }
-}
+/// How many image pixels a single arrow key press pans by
+const PAN_STEP: f32 = 16.0;
 
-#[derive(Debug, Clone)]
-struct PPMHeader {
-  ppm_type: PpmType,
-  width: i32,
-  height: i32,
-  max_value: i32,
-  data_position: usize,
-}
+/// Quality of JPEG files written by `convert` unless --quality is given
+#[cfg(feature = "export")]
+const DEFAULT_JPEG_QUALITY: u8 = 90;
 
-#[derive(Debug, Clone)]
-struct PPM {
-    header: PPMHeader,
-    values: Vec<PpmValue>,
-}
+/// Size in surface pixels of the squares of the checkerboard shown through transparent pixels
+const CHECKER_SIZE: u32 = 8;
+const CHECKER_LIGHT: u8 = 0xcc;
+const CHECKER_DARK: u8 = 0x99;
+
+/// Size in surface pixels of the histogram graph, and its distance from the window edges
+const HISTOGRAM_WIDTH: u32 = 256;
+const HISTOGRAM_HEIGHT: u32 = 100;
+const HISTOGRAM_MARGIN: u32 = 8;
+
+/// Height in surface pixels of the status line along the bottom of the window, a line of
+/// text with a little space around it
+const STATUS_PADDING: u32 = 3;
+const STATUS_HEIGHT: u32 = font::CELL_HEIGHT as u32 + 2 * STATUS_PADDING;
+
+/// Every how many surface pixels the rule-of-thirds guides draw a dot
+const GUIDE_DOT_SPACING: usize = 3;
+
+/// How often the file is checked for changes with --watch
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
 
-#[derive(Debug, Clone)]
-struct PpmValue {
-    r: i32,
-    g: i32,
-    b: i32
+/// Largest width or height of the window and its pixel buffer, the largest texture every GPU
+/// supports. Larger images are scaled down to fit it
+const MAX_TEXTURE_SIZE: u32 = 8192;
+
+/// Frame rate animations play at unless --fps is given
+const DEFAULT_FPS: f32 = 24.0;
+
+/// File extensions picked up when a directory is opened as a slideshow
+const SLIDESHOW_EXTENSIONS: [&str; 3] = ["ppm", "pgm", "pbm"];
+
+/// Range and step size of the gamma correction adjusted with the bracket keys
+const MIN_GAMMA: f32 = 0.1;
+const MAX_GAMMA: f32 = 4.0;
+const GAMMA_STEP: f32 = 0.1;
+
+/// Range and step size of the brightness offset adjusted with `,` and `.`
+const MAX_BRIGHTNESS: i32 = 255;
+const BRIGHTNESS_STEP: i32 = 8;
+
+/// Range and step size of the contrast factor adjusted with `<` and `>`
+const MIN_CONTRAST: f32 = 0.1;
+const MAX_CONTRAST: f32 = 4.0;
+const CONTRAST_STEP: f32 = 0.1;
+
+/// Which color channels of the image are shown
+#[derive(Clone, Copy, PartialEq)]
+enum Channels {
+    All,
+    Red,
+    Green,
+    Blue,
 }
 
-impl PpmValue {
-    fn new(red: i32, green: i32, blue: i32) -> Self {
-        PpmValue {
-            r: red,
-            g: green,
-            b: blue
-        }
-    } 
+/// How the image is sampled when it is scaled, set with --filter
+#[derive(Clone, Copy, PartialEq)]
+enum Filter {
+    /// every surface pixel shows the closest image pixel, keeping pixel art crisp
+    Nearest,
+    /// every surface pixel blends the four closest image pixels, smoother for photos
+    Bilinear,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-/// Determines the format type of file based on the the first two bytes
-/// of the Magic Number
-enum PpmType {
-    /// P1 is the Bitmap Data in ASCII
-    P1,
-    /// P2 is the Grayscale Data in ASCII
-    P2,
-    /// P3 is the RGB Image data in ASCII
-    P3,
-    /// P4 is the Bitmap Data in Binary Format
-    P4,
-    /// P5 is the Grayscale Data in Binary Format
-    P5,
-    /// P6 is the RGB Image Data in Binary Format
-    P6,
-    /// This is not a valid PPM/PGM/PBM File Format 
-    P0,
+/// How the pixel buffer's texture interprets the values drawn into it, set with --colorspace
+#[derive(Clone, Copy, PartialEq)]
+enum ColorSpace {
+    /// the values are sRGB encoded, and decoded by the GPU when sampled
+    Srgb,
+    /// the values are used as they are
+    Linear,
 }
 
-impl PPMHeader {
-  fn new() -> Self {
-    PPMHeader {
-      ppm_type: PpmType::P0,
-      width: 0,
-      height: 0,
-      max_value: 0,
-      data_position: 0,
+impl ColorSpace {
+    fn texture_format(self) -> wgpu::TextureFormat {
+        match self {
+            ColorSpace::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            ColorSpace::Linear => wgpu::TextureFormat::Rgba8Unorm,
+        }
     }
-  }
-}
 
-impl PPM {
-    fn new() -> Self {
-        PPM {
-            header: PPMHeader::new(),
-            values: Vec::new(),
-        }
-    }
-}
-
-/// Reads the ASCII file. Right now we just return the values. This is done because we've already built the header data.
-/// However, we have to read over the header, and parse data for now. @TODO: Fix this.
-fn read_ppm_ascii_file(path: &str, ppm_type: PpmType) -> Vec<PpmValue> {
-    let file = File::open(path).unwrap();
-    let mut dat : PPM = PPM::new();
-    dat.header.ppm_type = ppm_type;
-    let reader = BufReader::new(file);
-
-    let mut skip_first_line : bool = false;
-    for line in reader.lines() {
-        //println!("{}", line?);
-        let va = line.unwrap_or_default();
-        // we just skip over this line since we already have the ppm type
-        if !skip_first_line {
-            skip_first_line = true;
-            continue
-        }
-        // determine if there is a comment at the start of the line;
-        if va.clone().chars().next().unwrap_or_default() == '#' {
-            //println!("Found Comment on => {:?}", va.clone());
-            continue
-        }
-
-        if dat.header.width == 0 && dat.header.height == 0 {
-            let bar : Vec<i32> = va.split(' ').map(|x| x.parse::<i32>().unwrap()).collect();
-            dat.header.width = bar[0];
-
-            dat.header.height = bar[1];
-            //println!("This is width & Height: {:?}", bar);
-            continue
-        }
-
-        if dat.header.max_value == 0 && dat.header.ppm_type != PpmType::P1 {
-            dat.header.max_value = va.parse::<i32>().unwrap_or_default();
-            //println!("This is width & Height: {:?}", dat.max_value);
-            continue
-        }
-        let offset : usize = if va.clone().find('#').unwrap_or_default() == 0  {
-            va.clone().len()
-         } else {
-            va.clone().find('#').unwrap_or_default()
-         };
-
-        let x : Vec<i32> = (&va[0..offset]).split_whitespace().map(|x| x.parse::<i32>().unwrap()).collect();
-        if dat.header.ppm_type == PpmType::P3 {
-            dat.values.push(PpmValue::new(x[0], x[1], x[2]));
-        }
-        else if dat.header.ppm_type == PpmType::P2 {
-            for val in x {
-                dat.values.push(PpmValue::new(
-                  ((val as f32/dat.header.max_value as f32) * 255.0) as i32, 
-                  ((val as f32/dat.header.max_value as f32) * 255.0) as i32, 
-                  ((val as f32/dat.header.max_value as f32) * 255.0) as i32
-                ));
-                //println!("{:?} / {:?} = {:?}", val as f32, dat.max_value as f32, ((val as f32/dat.max_value as f32) * 255.0) as i32);
-            }
-        } else if dat.header.ppm_type == PpmType::P1 {
-            for val in x {
-                let pixel_data = if val == 0 {
-                    0
-                } else {
-                    255
-                };
-                dat.values.push(PpmValue::new(pixel_data, pixel_data, pixel_data));
-                //println!("{:?} / {:?} = {:?}", val as f32, dat.max_value as f32, ((val as f32/dat.max_value as f32) * 255.0) as i32);
-            }
+    fn name(self) -> &'static str {
+        match self {
+            ColorSpace::Srgb => "srgb",
+            ColorSpace::Linear => "linear",
         }
     }
-    dat.values
 }
 
-/// Given a path, it will parse the header information for the PPM family of files
-/// and returns the byte position where the header ends as well as the data inside
-/// the header object. 
-fn read_ppm_header(path: &str) -> PPMHeader {
-    let mut f = File::open(path).unwrap();
-    let mut byte_position: usize = 0;
+/// Representation of the application state
+struct World {
+    /// every image decoded from the file, more than one when it's an animation
+    frames: Vec<Image>,
+    /// which entry of `frames` is currently shown
+    frame_index: usize,
+    /// how many frames of an animation are shown per second
+    fps: f32,
+    /// stop the animation on the current frame
+    paused: bool,
+    /// when the animation moves on to the next frame
+    next_frame_at: Instant,
+    /// the file the frames were loaded from
+    path: PathBuf,
+    single_draw: bool,
+    has_been_drawn: bool,
+    /// color used for any part of the surface that has no decoded pixel behind it
+    fill_color: [u8; 4],
+    /// draw the image 1:1 instead of scaling it to fit the window
+    native: bool,
+    /// size of the pixel buffer being drawn into
+    surface_width: u32,
+    surface_height: u32,
+    /// color of the bars around the image when its aspect ratio doesn't match the window
+    letterbox_color: [u8; 4],
+    /// color shown through transparent pixels, a checkerboard is drawn when there is none
+    background: Option<[u8; 4]>,
+    /// magnification applied on top of the fit-to-window scale
+    zoom: f32,
+    /// whole number the window size is multiplied by, set with --scale
+    scale_factor: u32,
+    /// largest size the window is opened or resized to, as width and height
+    max_window_size: Option<(u32, u32)>,
+    /// size the window is always opened at, set with --window
+    fixed_window_size: Option<(u32, u32)>,
+    /// draw the image 1:1 in the middle of the window instead of scaling it to fit
+    center: bool,
+    /// offset, in image pixels, of the center of the view from the center of the image
+    pan_x: f32,
+    pan_y: f32,
+    /// print the value of the pixel under the cursor
+    inspect: bool,
+    /// the image pixel last reported by the inspector, so it only prints on change
+    last_inspected: Option<Option<(u32, u32)>>,
+    /// the decoded pixels of the frame at the given index as RGBA, see `World::update_rgba`.
+    /// For an image opened with `mmap::decode_rgba8` this is the only copy of its pixels.
+    rgba: Option<(usize, Vec<u8>)>,
+    /// draw the histogram of the current frame in a corner of the window
+    show_histogram: bool,
+    /// draw a status line with the decoded and expected pixel count along the bottom
+    show_status: bool,
+    /// histogram of the frame at the given index, see `World::histogram`
+    histogram: Option<(usize, Vec<[u32; 256]>)>,
+    /// draw a crosshair at the center of the view and rule-of-thirds lines over the image
+    guides: bool,
+    /// repeat the image across the window at its own size instead of scaling it to fit
+    tile: bool,
+    /// whether --tile was given, which resetting the view goes back to
+    start_tile: bool,
+    /// show the negative of the image
+    inverted: bool,
+    /// show the luminance of the image instead of its colors
+    grayscale: bool,
+    /// show only one color channel, with the others zeroed
+    channels: Channels,
+    /// gamma correction applied at draw time
+    gamma: f32,
+    /// color space of the pixel buffer's texture
+    colorspace: ColorSpace,
+    /// how finished frames are handed to the screen, Fifo being vsync
+    present_mode: wgpu::PresentMode,
+    /// how the image is sampled when scaled
+    filter: Filter,
+    /// offset added to every channel at draw time
+    brightness: i32,
+    /// factor every channel's distance from the midpoint is scaled by at draw time
+    contrast: f32,
+    /// lookup table for `gamma`, `contrast` and `brightness` together, indexed by channel value
+    tone_table: [u8; 256],
+    /// clockwise rotation of the view in degrees, one of 0, 90, 180 or 270
+    rotation: u32,
+    /// the rotation given with --rotate, which resetting the view goes back to
+    start_rotation: u32,
+    /// mirror the view left to right, and top to bottom
+    flip_horizontal: bool,
+    flip_vertical: bool,
+    /// the images of the directory being shown as a slideshow, empty when showing a single file
+    playlist: Vec<PathBuf>,
+    /// which entry of `playlist` is currently shown
+    playlist_index: usize,
+    /// how closely files have to follow the spec, set with --strict and --lenient
+    strictness: Strictness,
+    /// reload the image whenever the file it came from changes
+    watch: bool,
+    /// modification time of the file when it was last loaded
+    modified: Option<SystemTime>,
+    /// when the file was last checked for changes
+    last_watch_check: Instant,
+    /// modification time of the last version that failed to decode, so it's only reported once
+    failed_modified: Option<SystemTime>,
+}
+
+fn main() -> Result<(), Error> {
+    // get the arguments from the command line
+    let args: Vec<String> = env::args().collect();
+    init_logging(&args);
 
-    let mut header: PPMHeader = PPMHeader::new();
-    let mut magic_number = [0; 2];
+    if args.get(1).map(String::as_str) == Some("convert") {
+        std::process::exit(if convert(&args[2..]) { 0 } else { 1 });
+    }
 
-    // special values are the white spaces (in ascii, and '#', which demarks the start of a comment)
-    let special_values = [[10], [32], [35], [13]];
+    let options = parse_options(&args[1..]);
+    let validate_only = options.validate;
+    let native = options.native;
+    let filename = options.filename.as_str();
 
-    /*#region Get the type of PPM file */
-    // Get the type of PPM file we are reading
-    f.read_exact(&mut magic_number).unwrap();
-    let ppm_type = match magic_number {
-        [80, 49] => { PpmType::P1 },
-        [80, 50] => { PpmType::P2 },
-        [80, 51] => { PpmType::P3 },
-        [80, 52] => { PpmType::P4 },
-        [80, 53] => { PpmType::P5 },
-        [80, 54] => { PpmType::P6 },
-        _ => { PpmType::P0 }
+    let mut world = World::new();
+    world.scale_factor = options.scale;
+    world.set_gamma(options.gamma);
+    world.colorspace = options.colorspace;
+    world.present_mode = options.present_mode;
+    world.filter = options.filter;
+    world.strictness = options.strictness;
+    if let Some(background) = options.background {
+        world.set_background(background);
+    }
+    if let Some(missing_color) = options.missing_color {
+        world.fill_color = missing_color;
+    }
+    
+    // a directory is shown as a slideshow, starting with its first image, and so are the
+    // files listed in a response file given as @list.txt
+    let playlist = if let Some(list) = filename.strip_prefix('@') {
+        Some(read_response_file(Path::new(list)).map_err(|e| format!("could not read {}: {}", list, e)))
+    } else if Path::new(filename).is_dir() {
+        Some(collect_playlist(Path::new(filename)).map_err(|e| format!("could not read directory {}: {}", filename, e)))
+    } else {
+        None
     };
-    header.ppm_type = ppm_type;
-    byte_position += 2;
-    /* #endregion */
-
-    // if we have found an ASCII ppm file (p3) then we pass this data onto 
-    let mut byte_for = [0; 1]; // important note: 0x32 is the whitespace code.
-    while let Ok(n) = f.read(&mut byte_for) {
-        if header.width != 0 && header.height != 0 && (header.max_value != 0 || (header.ppm_type == PpmType::P1 || header.ppm_type == PpmType::P4)) {
-            byte_position += 1;
-            break;
-        }
-        if n != 0 {
-            // we need to find out something
-            let mut number_byte = Vec::new(); // important note: 0x32 is the whitespace code.
-
-            // ensure we don't double read over an actual piece of information
-            if !special_values.contains(&byte_for) {
-                number_byte.push(byte_for[0]);
-                byte_position += 1;
-            }
-
-            /* #region Read until whitespace */
-            // TODO: Test this - I'm not sure it will work for comments inside PPM files
-            // read bytes until whitespace or \n
-            while let Ok(n) = f.read(&mut byte_for) {
-                if n != 0 {
-                    // we increment the byte position regardless of what type
-                    byte_position += 1;
-                    
-                    // which byte is found?
-                    match byte_for {
-                        [10] | [13] | [32] => { // any of these indicates whitespace
-                            break;
-                        },
-                        // we have encountered a comment, read until a line break
-                        [35] => {
-                            while let Ok(z) = f.read(&mut byte_for) {
-                                if z!= 0 {
-                                    byte_position += 1;
-                                    if byte_for == [35] || byte_for == [13] || byte_for == [10] {
-                                        break;
-                                    }
-                                }
-                                else {
-                                    break;
-                                }
-                            }
-                            continue
-                        }
-                        _ => {
-                            number_byte.push(byte_for[0]);
-                            continue
-                        },
-                    }
-                } else {
-                    break
+    let filename = match playlist {
+        Some(Ok(playlist)) => {
+            world.playlist = playlist;
+            match world.playlist.first() {
+                Some(first) => first.to_string_lossy().into_owned(),
+                None => {
+                    error!("no images to show in {}", filename.trim_start_matches('@'));
+                    std::process::exit(1);
                 }
             }
-            /* #endregion */
+        }
+        Some(Err(message)) => {
+            error!("{}", message);
+            std::process::exit(1);
+        }
+        None => filename.to_owned(),
+    };
+    let filename = filename.as_str();
 
-            // we need to load up data;
-            // converts byte array into integer values
-            if header.width == 0 {
-                header.width = String::from_utf8_lossy(&number_byte).parse::<i32>().unwrap_or_default();
-                continue
-            }
-            if header.height == 0 {
-                header.height = String::from_utf8_lossy(&number_byte).parse::<i32>().unwrap_or_default();
-                continue
-            }
-            if header.max_value == 0 && (header.ppm_type != PpmType::P1 && header.ppm_type != PpmType::P4) {
-                header.max_value = String::from_utf8_lossy(&number_byte).parse::<i32>().unwrap_or_default();
-                continue
-            }
+    // "-" reads the image from stdin, e.g. `cat foo.ppm | ppmviewer -`
+    let from_stdin = filename == "-";
+
+    // headless mode that only reads the header, so it's instant even for huge files
+    if options.info {
+        let started = Instant::now();
+        let header = if from_stdin {
+            read_header_with(io::stdin().lock(), options.strictness)
         } else {
-            break
+            File::open(filename).map_err(PpmError::from).and_then(|file| read_header_with(file, options.strictness))
+        };
+        if options.timing {
+            // a header is far too small for a meaningful throughput
+            print_timing("read the header", None, started.elapsed());
         }
+        std::process::exit(if print_info(header) { 0 } else { 1 });
     }
-    
-    header.data_position = byte_position;
-    header
-}
 
-fn read_ppm_binary_image_data(path: &str, ppm_object: PPM) -> Vec<PpmValue> {
+    // only the window can make do with the pixels as RGBA, every other mode needs the images
+    let window_only = !validate_only && options.compare.is_none() && options.dump_rgba.is_none();
+    #[cfg(feature = "data-url")]
+    let window_only = window_only && !options.data_url;
+    let started = Instant::now();
+    let decoded = if from_stdin {
+        decode_all_with(io::stdin().lock(), world.strictness).map(|frames| (frames, None))
+    } else if window_only {
+        decode_for_window(Path::new(filename), world.strictness)
+    } else {
+        decode_file(Path::new(filename), world.strictness).map(|frames| (frames, None))
+    };
+    let decode_time = started.elapsed();
+    debug!("decoding took {:.1} ms", decode_time.as_secs_f64() * 1000.0);
+    let (frames, rgba) = match decoded {
+        Ok(decoded) => decoded,
+        // show what the file starts with instead, to help figure out what it is
+        Err(e @ PpmError::NotNetpbm(_)) | Err(e @ PpmError::TooShort) if options.hexdump_on_error && !from_stdin => {
+            error!("could not open {}: {}", filename, e);
+            let mut head = Vec::new();
+            if let Err(e) = File::open(filename).and_then(|file| file.take(hexdump::HEXDUMP_BYTES).read_to_end(&mut head)) {
+                error!("could not read {}: {}", filename, e);
+                std::process::exit(1);
+            }
+            (vec![hexdump::hexdump_image(&head)], None)
+        }
+        Err(e) => {
+            error!("could not open {}: {}", if from_stdin { "stdin" } else { filename }, e);
+            std::process::exit(1);
+        }
+    };
 
-    // open the file
-    let mut f = File::open(path).unwrap();
-    
-    // seek to the correct position for the image data to start
-    f.seek(SeekFrom::Start((ppm_object.header.data_position) as u64)).unwrap();
-
-    // create a new vector of PpmValues
-    let mut img_data = Vec::<PpmValue>::new();
-
-    if ppm_object.header.ppm_type == PpmType::P6 {
-        let mut byte_for = [0; 3]; // important note: 0x32 is the whitespace code.
-        while let Ok(n) = f.read(&mut byte_for) {
-            if n != 0 {
-                img_data.push(PpmValue::new(
-                  i32::from_be_bytes([0,0,0,byte_for[0]]),
-                  i32::from_be_bytes([0,0,0,byte_for[1]]), 
-                  i32::from_be_bytes([0,0,0,byte_for[2]])
-                ));
-            }
-            else {
-                break;
-            }
-        }
-    } else if ppm_object.header.ppm_type == PpmType::P5 {
-        let mut byte_for = [0; 1]; // important note: 0x32 is the whitespace code.
-        while let Ok(n) = f.read(&mut byte_for) {
-            if n != 0 {
-                let gs_data = i32::from_be_bytes([0,0,0,byte_for[0]]);
-                img_data.push(PpmValue::new(
-                  ((gs_data as f32 / ppm_object.header.max_value as f32) * 255.0) as i32,
-                  ((gs_data as f32 / ppm_object.header.max_value as f32) * 255.0) as i32,
-                  ((gs_data as f32 / ppm_object.header.max_value as f32) * 255.0) as i32
-                ));
-            }
-            else {
-                break;
-            }
-        }
-    } else if ppm_object.header.ppm_type == PpmType::P4 {
-        let mut byte_for = [0; 1]; // important note: 0x32 is the whitespace code.
-        while let Ok(n) = f.read(&mut byte_for) {
-            if n != 0 {
-                for i in (0..8).rev() {
-                    
-                    let pixel_data = get_bit_at(byte_for[0], i).unwrap();
-
-                    let final_value = if pixel_data {
-                        0
-                    } else {
-                        255
-                    };
-
-                    img_data.push(PpmValue::new(
-                      final_value, 
-                      final_value, 
-                      final_value)
-                    );
-                    //println!("{:?} => {:?} = {:?}", byte_for[0], i, get_bit_at(byte_for[0] as u32, i).unwrap());
+    // compare mode shows where the two images differ instead of either of them
+    let frames = match &options.compare {
+        Some(other) => {
+            let other_frames = match decode_file(Path::new(other), world.strictness) {
+                Ok(frames) => frames,
+                Err(e) => {
+                    error!("could not open {}: {}", other, e);
+                    std::process::exit(1);
                 }
+            };
+            let difference = match compare::compare(&frames[0], &other_frames[0]) {
+                Ok(difference) => difference,
+                Err(message) => {
+                    error!("could not compare {} with {}: {}", filename, other, message);
+                    std::process::exit(1);
+                }
+            };
+            compare::print_difference(&difference);
+            if validate_only {
+                std::process::exit(if difference.differing == 0 { 0 } else { 1 });
             }
-            else {
-                break;
-            }
+            vec![difference.heatmap]
         }
-    }
-
-    img_data
-}
+        None => frames,
+    };
 
-fn get_bit_at(input: u8, n: u8) -> Result<bool, ()> {
-    if n < 8 {
-        Ok(input & (1 << n) != 0)
-    } else {
-        Err(())
+    // headless mode, report on the file without ever creating a window
+    if validate_only {
+        if frames.len() > 1 {
+            println!("frames: {}", frames.len());
+        }
+        if options.timing {
+            // the size of the file, which stdin doesn't have
+            let file_bytes = if from_stdin { None } else { fs::metadata(filename).ok().map(|metadata| metadata.len()) };
+            print_timing("decoded", file_bytes, decode_time);
+        }
+        let invalid = frames.iter().filter(|frame| !validate(frame)).count();
+        std::process::exit(if invalid == 0 { 0 } else { 1 });
     }
-}
-
-fn main() -> Result<(), Error> {
-
-    // get the arguments from the command line
-    let args: Vec<String> = env::args().collect();
 
-    // require the filename
-    if args.len() <= 1 {
-        println!("File Name is required.");
-        std::process::exit(0);
+    // don't flash up an empty window for a file that decoded to nothing
+    let decoded_pixels = |frame: &Image| rgba.as_ref().map_or(frame.values.len(), |rgba| rgba.len() / 4);
+    if let Err(reason) = frames.iter().try_for_each(|frame| displayable(&frame.header, decoded_pixels(frame))) {
+        error!("nothing to display: {}", reason);
+        std::process::exit(1);
     }
 
-    let filename = &args[1];
-
-    if filename.is_empty() {
-        println!("File Name is required.");
-        std::process::exit(0);
+    // headless mode, write the raw pixels out for other tools instead of showing them
+    if let Some(output) = &options.dump_rgba {
+        std::process::exit(if dump_rgba(&frames[0], Path::new(output)) { 0 } else { 1 });
+    }
+    #[cfg(feature = "data-url")]
+    {
+        if options.data_url {
+            std::process::exit(if print_data_url(&frames[0]) { 0 } else { 1 });
+        }
     }
 
-    let mut world = World::new();
-    
-
-    let mut ppm = PPM::new();
-    let header = read_ppm_header(filename);
-    
-    
-    ppm.header = header.to_owned();
+    world.fps = options.fps;
+    world.show(frames, rgba);
+    world.native = native;
+    world.tile = options.tile;
+    world.start_tile = options.tile;
+    world.center = options.center;
+    world.fixed_window_size = options.window;
+    world.rotation = options.rotate;
+    world.start_rotation = options.rotate;
+    world.path = PathBuf::from(if from_stdin { "stdin" } else { filename });
+    if options.watch {
+        if options.compare.is_some() {
+            warn!("--watch has no effect with --compare");
+        } else if from_stdin {
+            warn!("--watch has no effect when reading from stdin");
+        } else {
+            world.watch = true;
+            world.modified = modified_time(&world.path);
+        }
+    }
 
-    if header.ppm_type == PpmType::P1 || header.ppm_type == PpmType::P2 || header.ppm_type == PpmType::P3 {
-      ppm.values = read_ppm_ascii_file(filename, header.clone().ppm_type);
-    } else if header.ppm_type == PpmType::P6 || header.ppm_type == PpmType::P5 || header.ppm_type == PpmType::P4 {
-        // there is an issue where byte were misaligned.
-        ppm.values = read_ppm_binary_image_data(filename, ppm.clone());
+    // the window can't be larger than the biggest texture the GPU is guaranteed to support.
+    // Images that are larger by themselves are scaled down to fit it, like they are to fit
+    // the screen, but magnifying one past it is a mistake
+    let (image_width, image_height) = world.displayed_size();
+    if image_width.max(image_height) > MAX_TEXTURE_SIZE {
+        info!(
+            "the {}x{} image is larger than the {} pixels across the GPU is guaranteed to support, it is scaled down to fit",
+            image_width, image_height, MAX_TEXTURE_SIZE
+        );
+    } else if world.fixed_window_size.is_none() && image_width.max(image_height).saturating_mul(world.scale_factor) > MAX_TEXTURE_SIZE {
+        error!(
+            "--scale {} would make the {}x{} image larger than {} pixels across",
+            world.scale_factor, image_width, image_height, MAX_TEXTURE_SIZE
+        );
+        std::process::exit(2);
     }
-    world.frame = Some(ppm);
 
-    let w_width = world.clone().get_width();
-    let w_height = world.clone().get_height();
-    env_logger::init();
     let event_loop = EventLoop::new();
+
+    // keep huge images from opening a window larger than the screen, they're scaled down
+    // to fit it instead
+    world.max_window_size = options.max_window_size.or_else(|| {
+        let monitor = event_loop.primary_monitor().or_else(|| event_loop.available_monitors().next())?;
+        let size: LogicalSize<u32> = monitor.size().to_logical(monitor.scale_factor());
+        Some((size.width, size.height))
+    });
+
+    let (w_width, w_height) = world.window_size();
     let mut input = WinitInputHelper::new();
     let window = {
         let size = LogicalSize::new(w_width as f64, w_height as f64);
-        WindowBuilder::new()
-            .with_title("PPMViewer - by github@VishalRamki")
-            .with_inner_size(size)
-            .with_min_inner_size(size)
-            .build(&event_loop)
-            .unwrap()
+        let builder = WindowBuilder::new()
+            .with_title(world.title())
+            .with_inner_size(size);
+        // when the image is scaled to the window, there's no reason to stop it from shrinking
+        let builder = if native { builder.with_min_inner_size(size) } else { builder };
+        builder.build(&event_loop).unwrap()
     };
 
     let mut pixels = {
         let window_size = window.inner_size();
         let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-        Pixels::new(w_width as u32, w_height as u32, surface_texture)?
+        if native {
+            world.surface_width = w_width;
+            world.surface_height = w_height;
+        } else {
+            world.surface_width = window_size.width;
+            world.surface_height = window_size.height;
+        }
+        new_pixels(&world, world.surface_width, world.surface_height, surface_texture)?
     };
 
     //let mut graphic = aci_ppm::decode(&input_f, afi::ColorChannels::Rgb).unwrap();
     //world.frame = graphic.pop();
     event_loop.run(move |event, _, control_flow| {
+        // once quitting, the window may still get a redraw or two while it's torn down, and
+        // rendering into a surface that is going away only fails
+        if *control_flow == ControlFlow::Exit {
+            return;
+        }
+
         // Draw the current frame
         if let Event::RedrawRequested(_) = event {
             world.draw(pixels.get_frame());
@@ -439,53 +485,1868 @@ fn main() -> Result<(), Error> {
 
         // Handle input events
         if input.update(&event) {
-            // Close events
-            if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
+            // Close events, Esc, Q and Ctrl+W quit like closing the window does
+            let close_pressed = input.key_pressed(VirtualKeyCode::W) && input.held_control();
+            if input.key_pressed(VirtualKeyCode::Escape) || input.key_pressed(VirtualKeyCode::Q) || close_pressed || input.quit() {
                 *control_flow = ControlFlow::Exit;
                 return;
             }
 
+            // Zoom in and out
+            if input.key_pressed(VirtualKeyCode::Equals) || input.key_pressed(VirtualKeyCode::NumpadAdd) {
+                world.zoom_in();
+            }
+            if input.key_pressed(VirtualKeyCode::Minus) || input.key_pressed(VirtualKeyCode::NumpadSubtract) {
+                world.zoom_out();
+            }
+
+            // Zoom with the mouse wheel, around whatever is under the cursor
+            let scroll = input.scroll_diff();
+            if scroll != 0.0 {
+                let factor = if scroll > 0.0 { WHEEL_ZOOM_FACTOR } else { 1.0 / WHEEL_ZOOM_FACTOR };
+                let (x, y) = match input.mouse() {
+                    Some(position) => {
+                        let (x, y) = pixels.window_pos_to_pixel(position).unwrap_or_else(|outside| pixels.clamp_pixel_pos(outside));
+                        (x as f32 + 0.5, y as f32 + 0.5)
+                    }
+                    None => (world.surface_width as f32 / 2.0, world.surface_height as f32 / 2.0),
+                };
+                world.zoom_at(world.zoom * factor, x, y);
+            }
+
+            // Pause and resume an animation
+            if input.key_pressed(VirtualKeyCode::Space) && world.frames.len() > 1 {
+                world.toggle_paused();
+                window.set_title(&world.title());
+            }
+
+            // Left and Right step through a paused animation, or switch images in a slideshow.
+            // They only do this while the image isn't zoomed in, otherwise they keep panning
+            // like they do for a single file.
+            let arrows_free = world.zoom <= 1.0;
+            let step_frames_with_arrows = arrows_free && world.frames.len() > 1 && world.paused;
+            let navigate_with_arrows = arrows_free && !step_frames_with_arrows && !world.playlist.is_empty();
+
+            let mut image_size_changed = false;
+            if step_frames_with_arrows && input.key_pressed(VirtualKeyCode::Right) {
+                image_size_changed |= world.step_frame(1);
+                window.set_title(&world.title());
+            }
+            if step_frames_with_arrows && input.key_pressed(VirtualKeyCode::Left) {
+                image_size_changed |= world.step_frame(-1);
+                window.set_title(&world.title());
+            }
+            if let Some(resized) = world.advance_animation() {
+                image_size_changed |= resized;
+                window.set_title(&world.title());
+            }
+
+            // Move through the slideshow
+            let step = if input.key_pressed(VirtualKeyCode::PageDown) || (navigate_with_arrows && input.key_pressed(VirtualKeyCode::Right)) {
+                1
+            } else if input.key_pressed(VirtualKeyCode::PageUp) || (navigate_with_arrows && input.key_pressed(VirtualKeyCode::Left)) {
+                -1
+            } else {
+                0
+            };
+            image_size_changed |= step != 0 && world.step_playlist(step);
+
+            // Home and End jump to the first and last image, unless zoomed in like the arrows
+            if navigate_with_arrows && input.key_pressed(VirtualKeyCode::Home) {
+                image_size_changed |= world.jump_playlist(false);
+            }
+            if navigate_with_arrows && input.key_pressed(VirtualKeyCode::End) {
+                image_size_changed |= world.jump_playlist(true);
+            }
+
+            // Rotate the view, R turns it clockwise and Shift+R counterclockwise
+            if input.key_pressed(VirtualKeyCode::R) {
+                world.rotate(if input.held_shift() { 270 } else { 90 });
+                image_size_changed = true;
+            }
+
+            // Undo every change to how the image is shown
+            if input.key_pressed(VirtualKeyCode::Back) {
+                image_size_changed |= world.reset_view();
+                window.set_title(&world.title());
+            }
+
+            // Mirror the view
+            if input.key_pressed(VirtualKeyCode::H) {
+                world.flip_horizontal = !world.flip_horizontal;
+                world.force_redraw();
+            }
+            if input.key_pressed(VirtualKeyCode::V) {
+                world.flip_vertical = !world.flip_vertical;
+                world.force_redraw();
+            }
+
+            // Pick up changes to the file
+            if let Some(resized) = world.reload_if_changed() {
+                window.set_title(&world.title());
+                image_size_changed |= resized;
+            }
+
+            if image_size_changed {
+                window.set_title(&world.title());
+                match fit_window_to_image(&mut world, &window) {
+                    Ok(Some(resized)) => pixels = resized,
+                    Ok(None) => {}
+                    Err(e) => {
+                        error!("could not resize the pixel buffer: {}", e);
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                }
+            }
+
+            // Pan around the image
+            let pan_with_arrows = !navigate_with_arrows && !step_frames_with_arrows;
+            if pan_with_arrows && input.key_pressed(VirtualKeyCode::Left) {
+                world.pan(-PAN_STEP, 0.0);
+            }
+            if pan_with_arrows && input.key_pressed(VirtualKeyCode::Right) {
+                world.pan(PAN_STEP, 0.0);
+            }
+            if input.key_pressed(VirtualKeyCode::Up) {
+                world.pan(0.0, -PAN_STEP);
+            }
+            if input.key_pressed(VirtualKeyCode::Down) {
+                world.pan(0.0, PAN_STEP);
+            }
+            if !navigate_with_arrows && input.key_pressed(VirtualKeyCode::Home) {
+                world.recenter();
+            }
+
+            // Drag the image around with the left mouse button
+            if input.mouse_held(0) {
+                let (dx, dy) = input.mouse_diff();
+                if dx != 0.0 || dy != 0.0 {
+                    // the mouse moves in window pixels, which only match the surface when the
+                    // pixel buffer is the size of the window
+                    let window_width = window.inner_size().width.max(1) as f32;
+                    let surface_per_window = world.surface_width as f32 / window_width;
+                    world.drag(dx * surface_per_window, dy * surface_per_window);
+                }
+            }
+
+            // Toggle the pixel inspector, and report whatever is under the cursor
+            if input.key_pressed(VirtualKeyCode::P) {
+                world.inspect = !world.inspect;
+                world.last_inspected = None;
+            }
+            if world.inspect {
+                if let Some(position) = input.mouse() {
+                    let source = pixels
+                        .window_pos_to_pixel(position)
+                        .ok()
+                        .and_then(|(x, y)| world.surface_to_source(x as u32, y as u32));
+                    world.report_pixel(source);
+                }
+            }
+
+            // Toggle the histogram, L as in levels
+            if input.key_pressed(VirtualKeyCode::L) {
+                world.show_histogram = !world.show_histogram;
+                world.force_redraw();
+            }
+
+            // Toggle the status line, B as in bar
+            if input.key_pressed(VirtualKeyCode::B) {
+                world.show_status = !world.show_status;
+                world.force_redraw();
+            }
+
+            // Toggle the composition guides
+            if input.key_pressed(VirtualKeyCode::T) {
+                world.guides = !world.guides;
+                world.force_redraw();
+            }
+
+            // Toggle repeating the image across the window
+            if input.key_pressed(VirtualKeyCode::X) {
+                world.tile = !world.tile;
+                world.force_redraw();
+            }
+
+            // Toggle showing the negative of the image
+            if input.key_pressed(VirtualKeyCode::I) {
+                world.inverted = !world.inverted;
+                world.force_redraw();
+            }
+
+            // Show a single color channel, or all of them again
+            for (key, channels) in [
+                (VirtualKeyCode::Key0, Channels::All),
+                (VirtualKeyCode::Key1, Channels::Red),
+                (VirtualKeyCode::Key2, Channels::Green),
+                (VirtualKeyCode::Key3, Channels::Blue),
+            ] {
+                if input.key_pressed(key) {
+                    world.channels = channels;
+                    world.force_redraw();
+                }
+            }
+
+            // Toggle showing the image in grayscale
+            if input.key_pressed(VirtualKeyCode::G) {
+                world.grayscale = !world.grayscale;
+                world.force_redraw();
+            }
+
+            // Adjust the gamma correction
+            if input.key_pressed(VirtualKeyCode::LBracket) {
+                world.set_gamma(world.gamma - GAMMA_STEP);
+                window.set_title(&world.title());
+            }
+            if input.key_pressed(VirtualKeyCode::RBracket) {
+                world.set_gamma(world.gamma + GAMMA_STEP);
+                window.set_title(&world.title());
+            }
+
+            // Adjust the brightness with , and ., and the contrast with < and > (the same keys
+            // with shift held)
+            let contrast_keys = input.held_shift();
+            if input.key_pressed(VirtualKeyCode::Comma) {
+                if contrast_keys {
+                    world.set_contrast(world.contrast - CONTRAST_STEP);
+                } else {
+                    world.set_brightness(world.brightness - BRIGHTNESS_STEP);
+                }
+                window.set_title(&world.title());
+            }
+            if input.key_pressed(VirtualKeyCode::Period) {
+                if contrast_keys {
+                    world.set_contrast(world.contrast + CONTRAST_STEP);
+                } else {
+                    world.set_brightness(world.brightness + BRIGHTNESS_STEP);
+                }
+                window.set_title(&world.title());
+            }
+
+            // Save what is currently on screen as a PNG
+            if input.key_pressed(VirtualKeyCode::S) {
+                match save_screenshot(&world.path, pixels.get_frame(), world.surface_width, world.surface_height) {
+                    Ok(path) => info!("saved {}", path.display()),
+                    Err(e) => error!("could not save screenshot: {}", e),
+                }
+            }
+
             // Resize the window
             // @TODO: Ensure we can resize the window;
             
             if let Some(size) = input.window_resized() {
-                //pixels.surface_size(size.width, size.height);
-                pixels.resize(size.width, size.height);
+                if world.native {
+                    pixels.resize(size.width, size.height);
+                } else if size.width > 0 && size.height > 0 {
+                    // the pixel buffer always matches the window, so it has to be rebuilt at the new size
+                    let surface_texture = SurfaceTexture::new(size.width, size.height, &window);
+                    match new_pixels(&world, size.width, size.height, surface_texture) {
+                        Ok(resized) => {
+                            pixels = resized;
+                            world.surface_width = size.width;
+                            world.surface_height = size.height;
+                            world.force_redraw();
+                        }
+                        Err(e) => {
+                            error!("could not resize the pixel buffer: {}", e);
+                            *control_flow = ControlFlow::Exit;
+                            return;
+                        }
+                    }
+                }
             }
             
             // Update internal state and request a redraw
             window.request_redraw();
+
+            // sleep until the next frame or watch check is due, or else until there's input
+            *control_flow = match world.next_wakeup() {
+                Some(deadline) => ControlFlow::WaitUntil(deadline),
+                None => ControlFlow::Wait,
+            };
         }
     });
 }
 
-impl World {
-    /// Create a new `World` instance that can draw a moving box.
-    fn new() -> Self {
-        Self {
-            frame: None,
-            single_draw: true,
-            has_been_drawn: false
-        }
+/// Creates a pixel buffer of the given size, with a texture in the color space chosen for `world`.
+fn new_pixels(world: &World, width: u32, height: u32, surface_texture: SurfaceTexture<'_, Window>) -> Result<Pixels<Window>, Error> {
+    PixelsBuilder::new(width, height, surface_texture)
+        .texture_format(world.colorspace.texture_format())
+        .present_mode(world.present_mode)
+        .build()
+}
+
+/// Resizes the window to the size the image is displayed at, after it changed. With `--native`
+/// the pixel buffer is the size of the image too, so a new one is returned in that case.
+fn fit_window_to_image(world: &mut World, window: &Window) -> Result<Option<Pixels<Window>>, Error> {
+    let (image_width, image_height) = world.window_size();
+    let size = LogicalSize::new(image_width as f64, image_height as f64);
+    window.set_inner_size(size);
+    if !world.native {
+        // the window resize event rebuilds the buffer if the size actually changed
+        return Ok(None);
     }
 
-    /// Draw the `World` state to the frame buffer.
-    ///
-    /// Assumes the default texture format: `wgpu::TextureFormat::Rgba8UnormSrgb`
-    fn draw(&mut self, frame: &mut [u8]) {
-        if self.single_draw && self.has_been_drawn {
-            return
+    window.set_min_inner_size(Some(size));
+    let window_size = window.inner_size();
+    let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, window);
+    let pixels = new_pixels(world, image_width.max(1), image_height.max(1), surface_texture)?;
+    world.surface_width = image_width;
+    world.surface_height = image_height;
+    world.force_redraw();
+    Ok(Some(pixels))
+}
+
+/// Maps a pixel of the image as displayed, rotated clockwise by `rotation` degrees, back to
+/// the pixel of the unrotated `width` x `height` image it shows.
+fn rotate_to_source(x: u32, y: u32, rotation: u32, width: u32, height: u32) -> (u32, u32) {
+    match rotation {
+        90 => (y, height - 1 - x),
+        180 => (width - 1 - x, height - 1 - y),
+        270 => (width - 1 - y, x),
+        _ => (x, y),
+    }
+}
+
+/// Decodes every image in the file at `path`, from a memory map when the mmap feature is enabled.
+fn decode_file(path: &Path, strictness: Strictness) -> Result<Vec<Image>, PpmError> {
+    // the memory mapped decoder only ever decodes with the default strictness
+    #[cfg(feature = "mmap")]
+    {
+        if strictness == Strictness::Normal {
+            return ppmviewer::mmap::decode_file(path);
         }
-        if self.frame.as_ref().unwrap().header.ppm_type != PpmType::P0 {
-            for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
-                let frame_instance = self.frame.as_ref().unwrap();
-                let rgba = [frame_instance.values[i].r as u8, frame_instance.values[i].g as u8, frame_instance.values[i].b as u8, 0xff];
-                pixel.copy_from_slice(&rgba);
+    }
+    File::open(path).map_err(PpmError::from).and_then(|file| decode_all_with(file, strictness))
+}
+
+/// Decodes the file at `path` to be shown in the window, see `World::show`. With the mmap
+/// feature a single binary image is converted straight to the RGBA the window draws from,
+/// and its frame only holds the header. Everything else is decoded with `decode_file`.
+fn decode_for_window(path: &Path, strictness: Strictness) -> Result<(Vec<Image>, Option<Vec<u8>>), PpmError> {
+    #[cfg(feature = "mmap")]
+    {
+        if strictness == Strictness::Normal {
+            if let Some((header, rgba)) = ppmviewer::mmap::decode_rgba8(path)? {
+                let mut image = Image::new();
+                image.header = header;
+                return Ok((vec![image], Some(rgba)));
             }
-        } 
+        }
+    }
+    decode_file(path, strictness).map(|frames| (frames, None))
+}
 
-        if self.single_draw && !self.has_been_drawn {
-            self.has_been_drawn = true;
+/// When the file at `path` was last modified, if that can be found out.
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Lists the images in a directory that can be shown as a slideshow, sorted by name.
+fn collect_playlist(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut playlist = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        // with the gzip feature, compressed images such as foo.ppm.gz are picked up as well
+        let mut name = path.to_path_buf();
+        if cfg!(feature = "gzip") && name.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("gz")) {
+            name.set_extension("");
+        }
+        let is_image = name
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| SLIDESHOW_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()))
+            .unwrap_or(false);
+        if is_image && path.is_file() {
+            playlist.push(path);
+        }
+    }
+    playlist.sort();
+    Ok(playlist)
+}
+
+/// Reads the images to show as a slideshow from a response file, one path per line in
+/// the order given. Blank lines and lines starting with '#' are skipped, and so are files
+/// that don't exist, with a warning.
+fn read_response_file(list: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut playlist = Vec::new();
+    for line in fs::read_to_string(list)?.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let path = PathBuf::from(line);
+        if path.is_file() {
+            playlist.push(path);
+        } else {
+            warn!("skipping {}, no such file", path.display());
+        }
+    }
+    Ok(playlist)
+}
+
+/// Builds a lookup table applying gamma, contrast and brightness to a channel value `c` in
+/// that order: the gamma curve `g = 255 * (c / 255) ^ (1 / gamma)`, then contrast scaling
+/// around the midpoint `(g - 128) * contrast + 128`, then `brightness` added as an offset.
+/// The result is clamped to 0..=255.
+fn tone_table(gamma: f32, contrast: f32, brightness: i32) -> [u8; 256] {
+    let mut table = [0; 256];
+    for (value, entry) in table.iter_mut().enumerate() {
+        let corrected = 255.0 * (value as f32 / 255.0).powf(1.0 / gamma);
+        // contrast stretches or squashes the values around the midpoint
+        let contrasted = (corrected - 128.0) * contrast + 128.0;
+        *entry = (contrasted + brightness as f32).round().clamp(0.0, 255.0) as u8;
+    }
+    table
+}
+
+/// Luminance of a color, using the Rec. 709 weights.
+fn luminance(red: u8, green: u8, blue: u8) -> u8 {
+    (0.2126 * f32::from(red) + 0.7152 * f32::from(green) + 0.0722 * f32::from(blue)).round() as u8
+}
+
+/// Picks a path next to the source file to save a screenshot to, e.g. foo.ppm.png.
+/// A counter is added (foo.ppm.1.png, foo.ppm.2.png, ...) so existing files are never overwritten.
+fn screenshot_path(source: &Path) -> PathBuf {
+    let mut name = source.as_os_str().to_owned();
+    name.push(".png");
+    let mut path = PathBuf::from(name);
+
+    let mut counter = 1;
+    while path.exists() {
+        let mut name = source.as_os_str().to_owned();
+        name.push(format!(".{}.png", counter));
+        path = PathBuf::from(name);
+        counter += 1;
+    }
+    path
+}
+
+/// Writes an RGBA8 framebuffer out as a PNG next to the source file, returning where it was saved.
+fn save_screenshot(source: &Path, frame: &[u8], width: u32, height: u32) -> image::ImageResult<PathBuf> {
+    let path = screenshot_path(source);
+    image::save_buffer(&path, frame, width, height, image::ColorType::Rgba8)?;
+    Ok(path)
+}
+
+/// Everything that can be set from the command line when viewing an image
+struct CommandLine {
+    /// the file, directory or "-" for stdin to show
+    filename: String,
+    /// the file to compare `filename` with, showing where they differ
+    compare: Option<String>,
+    validate: bool,
+    info: bool,
+    /// show the start of a file that isn't a Netpbm image as a hexdump instead of exiting
+    hexdump_on_error: bool,
+    /// print how long --info and --validate took to read the file
+    timing: bool,
+    /// where to write the raw RGBA pixels to instead of showing them
+    dump_rgba: Option<String>,
+    /// print the image as a PNG data URL instead of showing it
+    #[cfg(feature = "data-url")]
+    data_url: bool,
+    native: bool,
+    tile: bool,
+    center: bool,
+    /// clockwise rotation to open the image at, in degrees
+    rotate: u32,
+    watch: bool,
+    strictness: Strictness,
+    fps: f32,
+    /// integer magnification the window is opened at
+    scale: u32,
+    /// largest window size, the primary monitor's size when not given
+    max_window_size: Option<(u32, u32)>,
+    /// window size to use whatever the size of the image
+    window: Option<(u32, u32)>,
+    gamma: f32,
+    colorspace: ColorSpace,
+    present_mode: wgpu::PresentMode,
+    filter: Filter,
+    background: Option<[u8; 4]>,
+    /// color of the pixels missing from a truncated file, overriding the background
+    missing_color: Option<[u8; 4]>,
+}
+
+/// Sets up the logger, which every diagnostic goes through: errors and warnings show up as
+/// "error: ..." and "warning: ...", anything else as it is. Besides errors from other
+/// crates, the viewer's own messages down to info are shown. --quiet leaves only errors
+/// and --verbose adds debug messages, otherwise RUST_LOG can pick the levels. The flags are
+/// looked for before the options are parsed, so even problems with those are logged.
+fn init_logging(args: &[String]) {
+    let filters = if args.iter().any(|arg| arg == "--quiet") {
+        Some("error")
+    } else if args.iter().any(|arg| arg == "--verbose") {
+        Some("error,ppmviewer=debug")
+    } else {
+        None
+    };
+    let mut builder = match filters {
+        Some(filters) => {
+            let mut builder = env_logger::Builder::new();
+            builder.parse_filters(filters);
+            builder
         }
+        None => env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("error,ppmviewer=info")),
+    };
+    builder
+        .format(|buf, record| match record.level() {
+            log::Level::Error => writeln!(buf, "error: {}", record.args()),
+            log::Level::Warn => writeln!(buf, "warning: {}", record.args()),
+            _ => writeln!(buf, "{}", record.args()),
+        })
+        .init();
+}
+
+/// Prints how the viewer is used, to stderr when it's shown because of a mistake.
+fn print_usage(options: &getopts::Options, to_stderr: bool) {
+    let brief = "Usage: ppmviewer [options] FILE\n       ppmviewer --compare [options] FILE FILE\n       ppmviewer convert INPUT OUTPUT [--format P2|P3|P5|P6] [--output-format png|bmp|jpeg|tiff] [--quality 1-100]\n\n\
+                 FILE can be a PPM/PGM/PBM/PAM image, a directory to show as a slideshow, @LIST to show the files\n\
+                 listed one per line in LIST as a slideshow, or - to read from stdin.";
+    if to_stderr {
+        eprint!("{}", options.usage(brief));
+    } else {
+        print!("{}", options.usage(brief));
+    }
+}
+
+/// Parses a color given as six hex digits, e.g. "ff00ff", with or without a leading '#'.
+fn parse_hex_color(color: &str) -> Option<[u8; 4]> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?, 0xff])
+}
+
+/// Parses a size given as width and height separated by an 'x', e.g. "1920x1080". Both
+/// have to be at least 1.
+fn parse_size(size: &str) -> Option<(u32, u32)> {
+    let (width, height) = size.split_once(['x', 'X'])?;
+    match (width.trim().parse::<u32>().ok()?, height.trim().parse::<u32>().ok()?) {
+        (0, _) | (_, 0) => None,
+        (width, height) => Some((width, height)),
+    }
+}
+
+/// Parses the arguments for viewing an image (everything after the program name). Prints the
+/// usage and exits with code 2 when they're wrong, or with 0 after printing it for --help.
+fn parse_options(args: &[String]) -> CommandLine {
+    let mut options = getopts::Options::new();
+    options.optflag("h", "help", "print this help and exit");
+    options.optflag("", "info", "print the header as key=value lines to stderr without decoding the image, and exit");
+    options.optflag("", "validate", "print the decoded header and exit, with a non-zero code if the image can't be shown");
+    options.optflag("", "quiet", "only print errors");
+    options.optflag("", "verbose", "also print debug messages");
+    options.optflag("", "compare", "compare two images of the same type and size given as FILE FILE, print how much they differ and show a heatmap of the differences. With --validate only the summary is printed, and the exit code is non-zero if they differ");
+    options.optflag("", "timing", "with --info or --validate, also print how long reading the file took");
+    options.optopt("", "dump-rgba", "write the pixels to FILE as raw RGBA bytes, print the width and height and exit. Only the first image of an animation is written", "FILE");
+    options.optflag("", "data-url", "print the image as a base64 encoded PNG data URL (data:image/png;base64,...) and exit. Only the first image of an animation is printed");
+    options.optflag("", "hexdump-on-error", "when the file isn't a PPM/PGM/PBM/PAM image, show a hexdump of how it starts instead of exiting");
+    options.optflag("", "native", "draw the image 1:1 instead of scaling it to fit the window");
+    options.optopt("", "rotate", "open the image turned clockwise by 0, 90, 180 or 270 degrees", "DEGREES");
+    options.optflag("", "center", "draw the image 1:1 in the middle of the window, with the background around it");
+    options.optflag("", "tile", "repeat the image across the window, e.g. to check that a texture tiles seamlessly");
+    options.optflag("", "watch", "reload the image whenever the file changes");
+    options.optflag("", "strict", "reject anything the spec doesn't allow, like a raster with pixels missing or samples above the max value");
+    options.optflag("", "lenient", "tolerate common header mistakes, and show the pixels that decoded from a broken or half written raster instead of failing");
+    options.optopt("", "scale", "open the window FACTOR times the size of the image, each pixel drawn as a FACTOR x FACTOR block", "FACTOR");
+    options.optopt("", "max-window-size", "largest size to open the window at, larger images are scaled down to fit (default: the size of the screen)", "WxH");
+    options.optopt("", "window", "open the window at this size whatever the size of the image, e.g. to compare images side by side", "WxH");
+    options.optopt("", "gamma", "gamma correction to start with, 1.0 leaves the image as it is", "GAMMA");
+    options.optopt("", "colorspace", "how the GPU interprets the image values, srgb (default) or linear", "SPACE");
+    options.optopt("", "present-mode", "how frames are shown, fifo (vsync, default), mailbox or immediate", "MODE");
+    options.optflag("", "no-vsync", "show frames as soon as they're drawn, the same as --present-mode immediate");
+    options.optopt("", "filter", "how the image is sampled when scaled, nearest (default) or bilinear", "FILTER");
+    options.optopt("", "fps", "frame rate to play files holding several images at (default 24)", "FPS");
+    options.optopt("", "background", "color around the image, behind missing pixels and showing through transparent ones", "RRGGBB");
+    options.optopt("", "missing-color", "color of the pixels missing from a truncated file, e.g. ff00ff to make them stand out (default: black, or the background)", "RRGGBB");
+
+    let fail = |message: String| -> ! {
+        eprintln!("{}\n", message);
+        print_usage(&options, true);
+        std::process::exit(2);
+    };
+
+    let matches = match options.parse(args) {
+        Ok(matches) => matches,
+        Err(e) => fail(e.to_string()),
+    };
+    if matches.opt_present("help") {
+        print_usage(&options, false);
+        std::process::exit(0);
     }
-}
\ No newline at end of file
+
+    let number = |name: &str, default: f32| match matches.opt_str(name) {
+        None => default,
+        Some(value) => match value.parse::<f32>() {
+            Ok(number) if number > 0.0 && number.is_finite() => number,
+            _ => fail(format!("--{} must be a positive number, got {:?}", name, value)),
+        },
+    };
+    let scale = match matches.opt_str("scale") {
+        None => 1,
+        Some(value) => match value.parse::<u32>() {
+            Ok(scale) if (1..=MAX_TEXTURE_SIZE).contains(&scale) => scale,
+            _ => fail(format!("--scale must be a whole number of at least 1, got {:?}", value)),
+        },
+    };
+    let strictness = match (matches.opt_present("strict"), matches.opt_present("lenient")) {
+        (true, true) => fail(String::from("--strict and --lenient can't be used together")),
+        (true, false) => Strictness::Strict,
+        (false, true) => Strictness::Lenient,
+        (false, false) => Strictness::Normal,
+    };
+    let max_window_size = matches.opt_str("max-window-size").map(|size| {
+        parse_size(&size).unwrap_or_else(|| fail(format!("--max-window-size must be a size like 1920x1080, got {:?}", size)))
+    });
+    let window = matches.opt_str("window").map(|size| match parse_size(&size) {
+        Some((width, height)) if width.max(height) <= MAX_TEXTURE_SIZE => (width, height),
+        _ => fail(format!("--window must be a size like 640x480, up to {} pixels across, got {:?}", MAX_TEXTURE_SIZE, size)),
+    });
+    let gamma = number("gamma", 1.0);
+    let fps = number("fps", DEFAULT_FPS);
+    let colorspace = match matches.opt_str("colorspace").as_deref() {
+        None | Some("srgb") => ColorSpace::Srgb,
+        Some("linear") => ColorSpace::Linear,
+        Some(other) => fail(format!("--colorspace must be srgb or linear, got {:?}", other)),
+    };
+    let present_mode = match (matches.opt_present("no-vsync"), matches.opt_str("present-mode").as_deref()) {
+        (true, Some(_)) => fail(String::from("--no-vsync and --present-mode can't be used together")),
+        (false, None) | (false, Some("fifo")) => wgpu::PresentMode::Fifo,
+        (false, Some("mailbox")) => wgpu::PresentMode::Mailbox,
+        (true, None) | (false, Some("immediate")) => wgpu::PresentMode::Immediate,
+        (false, Some(other)) => fail(format!("--present-mode must be fifo, mailbox or immediate, got {:?}", other)),
+    };
+    let rotate = match matches.opt_str("rotate").as_deref() {
+        None => 0,
+        Some(value) => match value.parse::<u32>() {
+            Ok(degrees) if degrees % 90 == 0 && degrees < 360 => degrees,
+            _ => fail(format!("--rotate must be one of 0, 90, 180 or 270, got {:?}", value)),
+        },
+    };
+    #[cfg(not(feature = "data-url"))]
+    {
+        if matches.opt_present("data-url") {
+            fail(String::from("--data-url needs ppmviewer to be built with the data-url feature"));
+        }
+    }
+    let filter = match matches.opt_str("filter").as_deref() {
+        None | Some("nearest") => Filter::Nearest,
+        Some("bilinear") => Filter::Bilinear,
+        Some(other) => fail(format!("--filter must be nearest or bilinear, got {:?}", other)),
+    };
+    let background = matches.opt_str("background").map(|color| {
+        parse_hex_color(&color).unwrap_or_else(|| fail(format!("--background must be a hex color like ff00ff, got {:?}", color)))
+    });
+    let missing_color = matches.opt_str("missing-color").map(|color| {
+        parse_hex_color(&color).unwrap_or_else(|| fail(format!("--missing-color must be a hex color like ff00ff, got {:?}", color)))
+    });
+
+    let (filename, compare) = match (matches.opt_present("compare"), matches.free.as_slice()) {
+        (false, [filename]) if !filename.is_empty() => (filename.clone(), None),
+        (true, [filename, other]) if !filename.is_empty() && !other.is_empty() => (filename.clone(), Some(other.clone())),
+        (false, []) => fail(String::from("a file name is required")),
+        (false, _) => fail(format!("expected a single file name, got {}", matches.free.join(" "))),
+        (true, _) => fail(format!("--compare expects two file names, got {:?}", matches.free.join(" "))),
+    };
+
+    CommandLine {
+        filename,
+        compare,
+        validate: matches.opt_present("validate"),
+        info: matches.opt_present("info"),
+        timing: matches.opt_present("timing"),
+        dump_rgba: matches.opt_str("dump-rgba"),
+        #[cfg(feature = "data-url")]
+        data_url: matches.opt_present("data-url"),
+        hexdump_on_error: matches.opt_present("hexdump-on-error"),
+        native: matches.opt_present("native"),
+        tile: matches.opt_present("tile"),
+        center: matches.opt_present("center"),
+        rotate,
+        watch: matches.opt_present("watch"),
+        strictness,
+        fps,
+        scale,
+        max_window_size,
+        window,
+        gamma,
+        colorspace,
+        present_mode,
+        filter,
+        background,
+        missing_color,
+    }
+}
+
+/// What `convert` writes: one of the Netpbm formats, or with the export feature one of
+/// the formats the `image` crate can encode.
+enum OutputFormat {
+    Netpbm(PpmType),
+    #[cfg(feature = "export")]
+    Image(image::ImageFormat),
+}
+
+/// Picks the format to write from the extension of the output file: P6 for .ppm and P5 for
+/// .pgm, and with the export feature also .png, .bmp, .jpg/.jpeg and .tif/.tiff.
+fn format_from_extension(path: &Path) -> Option<OutputFormat> {
+    match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+        "ppm" => Some(OutputFormat::Netpbm(PpmType::P6)),
+        "pgm" => Some(OutputFormat::Netpbm(PpmType::P5)),
+        #[cfg(feature = "export")]
+        other => parse_image_format(other).map(OutputFormat::Image),
+        #[cfg(not(feature = "export"))]
+        _ => None,
+    }
+}
+
+/// Parses the value of `--output-format`, e.g. "png" or "JPEG".
+#[cfg(feature = "export")]
+fn parse_image_format(format: &str) -> Option<image::ImageFormat> {
+    match format.to_ascii_lowercase().as_str() {
+        "png" => Some(image::ImageFormat::Png),
+        "bmp" => Some(image::ImageFormat::Bmp),
+        "jpg" | "jpeg" => Some(image::ImageFormat::Jpeg),
+        "tif" | "tiff" => Some(image::ImageFormat::Tiff),
+        _ => None,
+    }
+}
+
+/// Parses the value of `--format`, e.g. "P2" or "p2". Only the formats the encoder can write are accepted.
+fn parse_format(format: &str) -> Option<PpmType> {
+    match format.to_ascii_uppercase().as_str() {
+        "P2" => Some(PpmType::P2),
+        "P3" => Some(PpmType::P3),
+        "P5" => Some(PpmType::P5),
+        "P6" => Some(PpmType::P6),
+        _ => None,
+    }
+}
+
+/// Runs `ppmviewer convert <input> <output> [--format P2|P3|P5|P6]`, decoding the input and
+/// writing it back out. Returns false, after printing why, if the conversion failed.
+fn convert(args: &[String]) -> bool {
+    let mut format = None;
+    #[cfg(feature = "export")]
+    let mut quality = DEFAULT_JPEG_QUALITY;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => match args.next().and_then(|value| parse_format(value)) {
+                Some(ppm_type) => format = Some(OutputFormat::Netpbm(ppm_type)),
+                None => {
+                    error!("--format must be one of P2, P3, P5 or P6");
+                    return false;
+                }
+            },
+            #[cfg(feature = "export")]
+            "--output-format" => match args.next().and_then(|value| parse_image_format(value)) {
+                Some(image_format) => format = Some(OutputFormat::Image(image_format)),
+                None => {
+                    error!("--output-format must be one of png, bmp, jpeg or tiff");
+                    return false;
+                }
+            },
+            #[cfg(feature = "export")]
+            "--quality" => match args.next().and_then(|value| value.parse().ok()).filter(|value| (1..=100).contains(value)) {
+                Some(value) => quality = value,
+                None => {
+                    error!("--quality must be a number from 1 to 100");
+                    return false;
+                }
+            },
+            // already taken care of by init_logging
+            "--quiet" | "--verbose" => {}
+            #[cfg(not(feature = "export"))]
+            "--output-format" | "--quality" => {
+                error!("{} needs ppmviewer to be built with the export feature", arg);
+                return false;
+            }
+            _ => paths.push(arg),
+        }
+    }
+
+    let (input, output) = match paths.as_slice() {
+        [input, output] => (input.as_str(), Path::new(output.as_str())),
+        _ => {
+            eprintln!("usage: ppmviewer convert <input> <output> [--format P2|P3|P5|P6] [--output-format png|bmp|jpeg|tiff] [--quality 1-100]");
+            return false;
+        }
+    };
+    let format = match format.or_else(|| format_from_extension(output)) {
+        Some(format) => format,
+        None => {
+            error!("can't tell which format to write {} as, pass --format", output.display());
+            return false;
+        }
+    };
+
+    let decoded = if input == "-" {
+        decode(io::stdin().lock())
+    } else {
+        File::open(input).map_err(PpmError::from).and_then(decode)
+    };
+    let image = match decoded {
+        Ok(image) => image,
+        Err(e) => {
+            error!("could not open {}: {}", input, e);
+            return false;
+        }
+    };
+
+    let written = match format {
+        OutputFormat::Netpbm(ppm_type) => File::create(output).and_then(|file| {
+            let mut writer = io::BufWriter::new(file);
+            encoder::encode(&mut writer, &image, &ppm_type)?;
+            writer.flush()
+        }).map_err(|e| e.to_string()),
+        #[cfg(feature = "export")]
+        OutputFormat::Image(image_format) => export(image, output, image_format, quality).map_err(|e| e.to_string()),
+    };
+    if let Err(e) = written {
+        error!("could not write {}: {}", output.display(), e);
+        return false;
+    }
+    true
+}
+
+/// Writes `image` with one of the encoders of the `image` crate. JPEG has no alpha channel,
+/// so it's written as RGB with the given quality.
+#[cfg(feature = "export")]
+fn export(image: Image, output: &Path, image_format: image::ImageFormat, quality: u8) -> image::ImageResult<()> {
+    if image_format != image::ImageFormat::Jpeg {
+        return image::RgbaImage::from(image).save_with_format(output, image_format);
+    }
+    let rgb = image::RgbImage::from(image);
+    let mut writer = io::BufWriter::new(File::create(output)?);
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut writer, quality)
+        .encode(&rgb, rgb.width(), rgb.height(), image::ColorType::Rgb8)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Prints how long reading took, with --timing, e.g. "decoded 12.4 MB in 83 ms (149 MB/s)".
+/// The throughput is left out when the number of bytes isn't known.
+fn print_timing(action: &str, bytes: Option<u64>, elapsed: Duration) {
+    let milliseconds = elapsed.as_secs_f64() * 1000.0;
+    match bytes {
+        Some(bytes) => {
+            let megabytes = bytes as f64 / 1_000_000.0;
+            let throughput = megabytes / elapsed.as_secs_f64().max(f64::EPSILON);
+            eprintln!("{} {:.1} MB in {:.0} ms ({:.0} MB/s)", action, megabytes, milliseconds, throughput);
+        }
+        None => eprintln!("{} in {:.0} ms", action, milliseconds),
+    }
+}
+
+/// Prints the header of a file to stderr as `key=value` lines, for scripts. Returns false,
+/// after printing why, if the header couldn't be read.
+fn print_info(header: Result<(usize, ImageHeader), PpmError>) -> bool {
+    match header {
+        Ok((header_bytes, header)) => {
+            eprintln!("type={}", header.ppm_type);
+            eprintln!("width={}", header.width);
+            eprintln!("height={}", header.height);
+            eprintln!("max_value={}", header.max_value);
+            eprintln!("depth={}", header.depth);
+            eprintln!("header_bytes={}", header_bytes);
+            true
+        }
+        Err(e) => {
+            if let PpmError::NotNetpbm(_) = e {
+                eprintln!("type=unknown");
+            }
+            eprintln!("error={}", e);
+            false
+        }
+    }
+}
+
+/// Checks that an image with `header` and `decoded_pixels` pixels decoded has something to
+/// draw, a supported type with sane dimensions and at least one decoded pixel. Returns why
+/// not otherwise.
+fn displayable(header: &ImageHeader, decoded_pixels: usize) -> Result<(), String> {
+    header.check().map_err(|e| e.to_string())?;
+    if decoded_pixels == 0 {
+        return Err(String::from("the file holds no pixel data"));
+    }
+    Ok(())
+}
+
+/// Writes the pixels of `image` to `path` as raw RGBA, 4 bytes per pixel row by row with
+/// nothing around them, and prints the dimensions needed to make sense of the file.
+fn dump_rgba(image: &Image, path: &Path) -> bool {
+    if let Err(e) = fs::write(path, image.to_rgba8()) {
+        error!("could not write {}: {}", path.display(), e);
+        return false;
+    }
+    println!("width: {}", image.header.width);
+    println!("height: {}", image.header.height);
+    true
+}
+
+/// Prints `image` as a data URL holding it as a base64 encoded PNG, which can be pasted
+/// straight into html or markdown, e.g. `data:image/png;base64,iVBORw0KGgo...`.
+#[cfg(feature = "data-url")]
+fn print_data_url(image: &Image) -> bool {
+    let (width, height) = (image.header.width.max(0) as u32, image.header.height.max(0) as u32);
+    let mut png = Vec::new();
+    if let Err(e) = image::codecs::png::PngEncoder::new(&mut png).encode(&image.to_rgba8(), width, height, image::ColorType::Rgba8) {
+        error!("could not encode the image as a PNG: {}", e);
+        return false;
+    }
+    println!("data:image/png;base64,{}", base64::encode(&png));
+    true
+}
+
+/// Prints what was decoded from the file and checks that it is actually displayable.
+/// Returns false if the image is not something the viewer could show.
+fn validate(image: &Image) -> bool {
+    let header = &image.header;
+    println!("type: {}", header.ppm_type);
+    println!("width: {}", header.width);
+    println!("height: {}", header.height);
+    println!("max_value: {}", header.max_value);
+    println!("pixels: {}", image.values.len());
+
+    if header.ppm_type == PpmType::P0 {
+        error!("not a PPM/PGM/PBM file");
+        return false;
+    }
+    let expected = header.pixel_count().unwrap_or(0);
+    if image.values.len() != expected {
+        error!("expected {} pixels for a {}x{} image but decoded {}", expected, header.width, header.height, image.values.len());
+        return false;
+    }
+    true
+}
+
+impl World {
+    /// Create a new `World` instance that can draw a moving box.
+    fn new() -> Self {
+        Self {
+            frames: Vec::new(),
+            frame_index: 0,
+            fps: DEFAULT_FPS,
+            paused: false,
+            next_frame_at: Instant::now(),
+            path: PathBuf::new(),
+            single_draw: true,
+            has_been_drawn: false,
+            fill_color: [0, 0, 0, 0xff],
+            native: false,
+            surface_width: 0,
+            surface_height: 0,
+            letterbox_color: [0, 0, 0, 0xff],
+            background: None,
+            zoom: 1.0,
+            scale_factor: 1,
+            max_window_size: None,
+            fixed_window_size: None,
+            center: false,
+            pan_x: 0.0,
+            pan_y: 0.0,
+            inspect: false,
+            last_inspected: None,
+            rgba: None,
+            show_histogram: false,
+            show_status: false,
+            histogram: None,
+            guides: false,
+            tile: false,
+            start_tile: false,
+            inverted: false,
+            grayscale: false,
+            channels: Channels::All,
+            gamma: 1.0,
+            colorspace: ColorSpace::Srgb,
+            present_mode: wgpu::PresentMode::Fifo,
+            filter: Filter::Nearest,
+            brightness: 0,
+            contrast: 1.0,
+            tone_table: tone_table(1.0, 1.0, 0),
+            rotation: 0,
+            start_rotation: 0,
+            flip_horizontal: false,
+            flip_vertical: false,
+            playlist: Vec::new(),
+            playlist_index: 0,
+            strictness: Strictness::Normal,
+            watch: false,
+            modified: None,
+            last_watch_check: Instant::now(),
+            failed_modified: None,
+        }
+    }
+
+    /// With --watch, checks whether the file changed since it was loaded and decodes it again
+    /// if so. A file that fails to decode is most likely still being written, so it's left
+    /// alone and tried again on the next check. Returns Some when the image was reloaded,
+    /// holding whether its size changed.
+    fn reload_if_changed(&mut self) -> Option<bool> {
+        if !self.watch || self.last_watch_check.elapsed() < WATCH_INTERVAL {
+            return None;
+        }
+        self.last_watch_check = Instant::now();
+
+        let modified = modified_time(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return None;
+        }
+        match decode_for_window(&self.path, self.strictness) {
+            Ok((frames, rgba)) => {
+                let old_size = self.image_size();
+                self.show(frames, rgba);
+                self.modified = modified;
+                self.failed_modified = None;
+                self.last_inspected = None;
+                self.force_redraw();
+                Some(self.image_size() != old_size)
+            }
+            Err(e) => {
+                if self.failed_modified != modified {
+                    self.failed_modified = modified;
+                    warn!("could not reload {}, will retry: {}", self.path.display(), e);
+                }
+                None
+            }
+        }
+    }
+
+    /// The image currently shown, the current frame of an animation.
+    fn frame(&self) -> Option<&Image> {
+        self.frames.get(self.frame_index)
+    }
+
+    /// Replaces what is shown with newly decoded frames, starting from the first.
+    fn set_frames(&mut self, frames: Vec<Image>) {
+        self.frames = frames;
+        self.frame_index = 0;
+        self.rgba = None;
+        self.histogram = None;
+        self.next_frame_at = Instant::now() + self.frame_duration();
+        self.force_redraw();
+    }
+
+    /// Shows what `decode_for_window` decoded: the frames, and for an image converted
+    /// straight to RGBA its pixels, which its frame doesn't hold.
+    fn show(&mut self, frames: Vec<Image>, rgba: Option<Vec<u8>>) {
+        self.set_frames(frames);
+        if let Some(rgba) = rgba {
+            self.rgba = Some((0, rgba));
+        }
+    }
+
+    /// How long each frame of an animation is shown for.
+    fn frame_duration(&self) -> Duration {
+        Duration::from_secs_f32(1.0 / self.fps)
+    }
+
+    fn toggle_paused(&mut self) {
+        self.paused = !self.paused;
+        self.next_frame_at = Instant::now() + self.frame_duration();
+    }
+
+    /// Moves `step` frames through the animation, wrapping around at either end.
+    /// Returns true if the new frame is a different size than the last.
+    fn step_frame(&mut self, step: isize) -> bool {
+        if self.frames.is_empty() {
+            return false;
+        }
+        let old_size = self.image_size();
+        self.frame_index = (self.frame_index as isize + step).rem_euclid(self.frames.len() as isize) as usize;
+        self.last_inspected = None;
+        self.force_redraw();
+        self.image_size() != old_size
+    }
+
+    /// Shows the next frame of a running animation once it is due. Returns Some when the
+    /// frame changed, holding whether its size changed.
+    fn advance_animation(&mut self) -> Option<bool> {
+        if self.frames.len() < 2 || self.paused || Instant::now() < self.next_frame_at {
+            return None;
+        }
+        self.next_frame_at += self.frame_duration();
+        // after falling behind (e.g. the window was being dragged) skip ahead instead of catching up
+        if self.next_frame_at < Instant::now() {
+            self.next_frame_at = Instant::now() + self.frame_duration();
+        }
+        Some(self.step_frame(1))
+    }
+
+    /// When the event loop next has to wake up by itself: for the next frame while an
+    /// animation is playing, and for the next check of the file with --watch. None when
+    /// nothing changes without input.
+    fn next_wakeup(&self) -> Option<Instant> {
+        let next_frame = if self.frames.len() < 2 || self.paused { None } else { Some(self.next_frame_at) };
+        let next_watch_check = if self.watch { Some(self.last_watch_check + WATCH_INTERVAL) } else { None };
+        match (next_frame, next_watch_check) {
+            (Some(next_frame), Some(next_watch_check)) => Some(next_frame.min(next_watch_check)),
+            (next_frame, next_watch_check) => next_frame.or(next_watch_check),
+        }
+    }
+
+    /// Moves `step` entries through the slideshow, wrapping around at either end. Files that
+    /// fail to decode are reported and skipped. Returns true if a different image is now shown.
+    fn step_playlist(&mut self, step: isize) -> bool {
+        let count = self.playlist.len() as isize;
+        let mut index = self.playlist_index as isize;
+        for _ in 1..count {
+            index = (index + step).rem_euclid(count);
+            if self.show_playlist_entry(index as usize) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Jumps to the first or last image of the slideshow, or the closest one to it that
+    /// decodes. Returns true if a different image is now shown.
+    fn jump_playlist(&mut self, to_last: bool) -> bool {
+        let count = self.playlist.len();
+        for offset in 0..count {
+            let index = if to_last { count - 1 - offset } else { offset };
+            if index == self.playlist_index {
+                return false;
+            }
+            if self.show_playlist_entry(index) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Shows the entry at `index` of the slideshow, with the view reset. Returns false, after
+    /// reporting why, if the file fails to decode.
+    fn show_playlist_entry(&mut self, index: usize) -> bool {
+        let path = self.playlist[index].clone();
+        match decode_for_window(&path, self.strictness) {
+            Ok((frames, rgba)) => {
+                self.show(frames, rgba);
+                self.modified = modified_time(&path);
+                self.path = path;
+                self.playlist_index = index;
+                self.zoom = 1.0;
+                self.recenter();
+                self.last_inspected = None;
+                true
+            }
+            Err(e) => {
+                warn!("could not open {}: {}", path.display(), e);
+                false
+            }
+        }
+    }
+
+    /// Sets the gamma correction, clamped to the allowed range.
+    fn set_gamma(&mut self, gamma: f32) {
+        // round off the float error that builds up from repeated steps
+        self.gamma = ((gamma * 10.0).round() / 10.0).clamp(MIN_GAMMA, MAX_GAMMA);
+        self.update_tone_table();
+    }
+
+    fn set_brightness(&mut self, brightness: i32) {
+        self.brightness = brightness.clamp(-MAX_BRIGHTNESS, MAX_BRIGHTNESS);
+        self.update_tone_table();
+    }
+
+    fn set_contrast(&mut self, contrast: f32) {
+        self.contrast = ((contrast * 10.0).round() / 10.0).clamp(MIN_CONTRAST, MAX_CONTRAST);
+        self.update_tone_table();
+    }
+
+    /// Gamma, contrast and brightness are all looked up in a single table, built again
+    /// whenever one of them changes.
+    fn update_tone_table(&mut self) {
+        self.tone_table = tone_table(self.gamma, self.contrast, self.brightness);
+        self.force_redraw();
+    }
+
+    /// Applies the display-only color adjustments to a pixel of the image.
+    /// The decoded values are never touched, only what ends up on screen.
+    fn adjust_color(&self, rgba: &[u8]) -> [u8; 4] {
+        let mut color = [rgba[0], rgba[1], rgba[2], rgba[3]];
+        match self.channels {
+            Channels::All => {}
+            Channels::Red => color = [color[0], 0, 0, color[3]],
+            Channels::Green => color = [0, color[1], 0, color[3]],
+            Channels::Blue => color = [0, 0, color[2], color[3]],
+        }
+        if self.grayscale {
+            let gray = luminance(color[0], color[1], color[2]);
+            color = [gray, gray, gray, color[3]];
+        }
+        for channel in color.iter_mut().take(3) {
+            *channel = self.tone_table[*channel as usize];
+        }
+        if self.inverted {
+            for channel in color.iter_mut().take(3) {
+                *channel = 255 - *channel;
+            }
+        }
+        color
+    }
+
+    /// Prints the position and value of the image pixel under the cursor, if it changed
+    /// since the last report. `source` is None when the cursor isn't over the image.
+    fn report_pixel(&mut self, source: Option<(u32, u32)>) {
+        if self.last_inspected == Some(source) {
+            return;
+        }
+        self.last_inspected = Some(source);
+
+        if self.frame().is_none() {
+            return;
+        }
+        let (width, _) = self.image_size();
+        match source {
+            Some((x, y)) => {
+                let start = (y as usize * width as usize + x as usize) * 4;
+                match self.rgba().get(start..start + 4) {
+                    Some(pixel) => println!("{},{}: r={} g={} b={} a={}", x, y, pixel[0], pixel[1], pixel[2], pixel[3]),
+                    None => println!("{},{}: not decoded", x, y),
+                }
+            }
+            None => println!("outside image"),
+        }
+    }
+
+    /// Turns the view clockwise by `degrees`, which should be a multiple of 90. The decoded
+    /// image is left as it is, only the way it is drawn changes.
+    fn rotate(&mut self, degrees: u32) {
+        self.rotation = (self.rotation + degrees) % 360;
+        self.recenter();
+    }
+
+    /// Moves the view by the given number of image pixels. The center of the view is kept
+    /// over the image, so it can never be scrolled entirely off-screen.
+    fn pan(&mut self, dx: f32, dy: f32) {
+        let (image_width, image_height) = self.displayed_size();
+        let half_width = image_width as f32 / 2.0;
+        let half_height = image_height as f32 / 2.0;
+        self.pan_x = (self.pan_x + dx).clamp(-half_width, half_width);
+        self.pan_y = (self.pan_y + dy).clamp(-half_height, half_height);
+        self.force_redraw();
+    }
+
+    /// Moves the image along with the mouse when it's dragged `dx`, `dy` surface pixels.
+    /// The pan is limited like it is for the arrow keys, so some of the image always stays
+    /// in view.
+    fn drag(&mut self, dx: f32, dy: f32) {
+        let scale = self.scale();
+        self.pan(-dx / scale, -dy / scale);
+    }
+
+    /// Puts every view transform back to how the image is first shown. Returns whether the
+    /// displayed size changed, which it does when the image was turned on its side compared
+    /// to the rotation it started with.
+    fn reset_view(&mut self) -> bool {
+        let rotated_on_side = self.rotation % 180 != self.start_rotation % 180;
+        self.zoom = 1.0;
+        self.pan_x = 0.0;
+        self.pan_y = 0.0;
+        self.rotation = self.start_rotation;
+        self.tile = self.start_tile;
+        self.flip_horizontal = false;
+        self.flip_vertical = false;
+        self.set_gamma(1.0);
+        self.set_brightness(0);
+        self.set_contrast(1.0);
+        self.inverted = false;
+        self.grayscale = false;
+        self.channels = Channels::All;
+        self.force_redraw();
+        rotated_on_side
+    }
+
+    /// Puts the image back in the center of the view.
+    fn recenter(&mut self) {
+        self.pan_x = 0.0;
+        self.pan_y = 0.0;
+        self.force_redraw();
+    }
+
+    /// Sets the zoom, clamped to the allowed range. Above 1.0 the zoom snaps to whole
+    /// numbers so every image pixel covers the same number of surface pixels.
+    fn set_zoom(&mut self, zoom: f32) {
+        let zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        self.zoom = if zoom > 1.0 { zoom.round() } else { zoom };
+        self.force_redraw();
+    }
+
+    /// Zooms to `zoom` while keeping the part of the image at the surface point `x`, `y`
+    /// where it is, e.g. under the mouse cursor.
+    fn zoom_at(&mut self, zoom: f32, x: f32, y: f32) {
+        let (before_x, before_y) = self.displayed_position(x, y);
+        self.set_zoom(zoom);
+        let (after_x, after_y) = self.displayed_position(x, y);
+        self.pan(before_x - after_x, before_y - after_y);
+    }
+
+    fn zoom_in(&mut self) {
+        if self.zoom >= 1.0 {
+            self.set_zoom(self.zoom + 1.0);
+        } else {
+            self.set_zoom(self.zoom + 0.1);
+        }
+    }
+
+    fn zoom_out(&mut self) {
+        if self.zoom > 1.0 {
+            self.set_zoom(self.zoom - 1.0);
+        } else {
+            self.set_zoom(self.zoom - 0.1);
+        }
+    }
+
+    /// Window title describing the file being shown, e.g. "foo.ppm — 640x480 P6 — PPMViewer"
+    fn title(&self) -> String {
+        let file_name = self.path.file_name().unwrap_or_else(|| self.path.as_os_str());
+        // in a slideshow, show where in it we are, e.g. "foo.ppm (3/10)"
+        let mut position = if self.playlist.is_empty() {
+            String::new()
+        } else {
+            format!(" ({}/{})", self.playlist_index + 1, self.playlist.len())
+        };
+        // and the same for the frames of an animation, e.g. "foo.ppm frame 4/24"
+        if self.frames.len() > 1 {
+            position.push_str(&format!(" frame {}/{}", self.frame_index + 1, self.frames.len()));
+            if self.paused {
+                position.push_str(" (paused)");
+            }
+        }
+        match self.frame() {
+            Some(frame) => format!(
+                "{}{} — {}x{} {} — gamma {:.1} brightness {:+} contrast {:.1} — {} — PPMViewer",
+                file_name.to_string_lossy(), position, frame.header.width, frame.header.height, frame.header.ppm_type, self.gamma,
+                self.brightness, self.contrast, self.colorspace.name()
+            ),
+            None => String::from("PPMViewer"),
+        }
+    }
+
+    /// Makes the next call to `draw()` redraw the frame, even when `single_draw` is set.
+    fn force_redraw(&mut self) {
+        self.has_been_drawn = false;
+    }
+
+    /// Width and height of the image, as given by its header.
+    fn image_size(&self) -> (u32, u32) {
+        match self.frame() {
+            Some(frame) => (frame.header.width.max(0) as u32, frame.header.height.max(0) as u32),
+            None => (0, 0),
+        }
+    }
+
+    /// Width and height of the image as it is drawn, swapped when it is rotated on its side.
+    fn displayed_size(&self) -> (u32, u32) {
+        let (image_width, image_height) = self.image_size();
+        if self.rotation == 90 || self.rotation == 270 {
+            (image_height, image_width)
+        } else {
+            (image_width, image_height)
+        }
+    }
+
+    /// Size the window is opened at, the displayed size multiplied by `scale_factor`. The
+    /// factor is lowered for images too large to be magnified that much, and anything larger
+    /// than `max_window_size` or `MAX_TEXTURE_SIZE`, whichever is smaller, is shrunk to fit
+    /// it, keeping the aspect ratio. The image is then scaled down to fit the window, also
+    /// with --native, since the pixel buffer follows the window size. A size given with
+    /// --window is used as it is.
+    fn window_size(&self) -> (u32, u32) {
+        if let Some(size) = self.fixed_window_size {
+            return size;
+        }
+        let (width, height) = self.displayed_size();
+        let largest_factor = MAX_TEXTURE_SIZE / width.max(height).max(1);
+        let factor = self.scale_factor.min(largest_factor).max(1);
+        let (width, height) = (width * factor, height * factor);
+        let (max_width, max_height) = self.max_window_size.unwrap_or((MAX_TEXTURE_SIZE, MAX_TEXTURE_SIZE));
+        let (max_width, max_height) = (max_width.min(MAX_TEXTURE_SIZE), max_height.min(MAX_TEXTURE_SIZE));
+        if width > max_width || height > max_height {
+            let ratio = (max_width as f32 / width as f32).min(max_height as f32 / height as f32);
+            (((width as f32 * ratio) as u32).max(1), ((height as f32 * ratio) as u32).max(1))
+        } else {
+            (width, height)
+        }
+    }
+
+    /// The uniform factor the image is scaled by to fit the surface without distorting it.
+    /// Tiles and centered images are drawn at their own size, only the zoom scales them.
+    fn fit_scale(&self) -> f32 {
+        if self.tile || self.center {
+            return 1.0;
+        }
+        let (image_width, image_height) = self.displayed_size();
+        if image_width == 0 || image_height == 0 {
+            return 1.0;
+        }
+        let width_ratio = self.surface_width as f32 / image_width as f32;
+        let height_ratio = self.surface_height as f32 / image_height as f32;
+        width_ratio.min(height_ratio)
+    }
+
+    /// The total factor the image is drawn at, including the zoom.
+    fn scale(&self) -> f32 {
+        self.fit_scale() * self.zoom
+    }
+
+    /// Where the center of a pixel on the surface lands on the image as it is displayed
+    /// (rotated and flipped), in fractional image pixels. Returns None when it lands on the
+    /// letterbox around the image. When tiling there is no letterbox, every point outside
+    /// the image wraps around to the copy of it drawn there.
+    fn surface_to_displayed(&self, x: u32, y: u32) -> Option<(f32, f32)> {
+        let (image_width, image_height) = self.displayed_size();
+        let (displayed_x, displayed_y) = self.displayed_position(x as f32 + 0.5, y as f32 + 0.5);
+        if self.tile && image_width > 0 && image_height > 0 {
+            let wrap = |value: f32, size: u32| value.rem_euclid(size as f32).min(size as f32 - 1.0);
+            return Some((wrap(displayed_x, image_width), wrap(displayed_y, image_height)));
+        }
+        if displayed_x < 0.0 || displayed_y < 0.0 || displayed_x >= image_width as f32 || displayed_y >= image_height as f32 {
+            return None;
+        }
+        Some((displayed_x, displayed_y))
+    }
+
+    /// Where a point on the surface lands on the image as it is displayed, both in fractional
+    /// pixels. Points outside the image are mapped as well.
+    fn displayed_position(&self, x: f32, y: f32) -> (f32, f32) {
+        let (image_width, image_height) = self.displayed_size();
+        let scale = self.scale();
+
+        // the scaled image is centered on the surface
+        let offset_x = (self.surface_width as f32 - image_width as f32 * scale) / 2.0;
+        let offset_y = (self.surface_height as f32 - image_height as f32 * scale) / 2.0;
+        ((x - offset_x) / scale + self.pan_x, (y - offset_y) / scale + self.pan_y)
+    }
+
+    /// Maps a pixel of the image as it is displayed back to the pixel of the decoded image.
+    fn displayed_to_source(&self, x: u32, y: u32) -> (u32, u32) {
+        let (image_width, image_height) = self.displayed_size();
+        // the flips mirror the image as it is shown, so they're undone before the rotation
+        let x = if self.flip_horizontal { image_width - 1 - x } else { x };
+        let y = if self.flip_vertical { image_height - 1 - y } else { y };
+        let (unrotated_width, unrotated_height) = self.image_size();
+        rotate_to_source(x, y, self.rotation, unrotated_width, unrotated_height)
+    }
+
+    /// Maps a pixel on the surface back to the image pixel drawn there (nearest neighbour).
+    /// Returns None when the surface pixel lands on the letterbox around the image.
+    fn surface_to_source(&self, x: u32, y: u32) -> Option<(u32, u32)> {
+        let (displayed_x, displayed_y) = self.surface_to_displayed(x, y)?;
+        Some(self.displayed_to_source(displayed_x as u32, displayed_y as u32))
+    }
+
+    /// Blends the four image pixels around the center of a surface pixel, weighted by how
+    /// close they are (bilinear filtering). Neighbours past the edge of the image are clamped
+    /// to the edge, or wrap around to the other side when tiling so seams show up, and
+    /// pixels missing from a truncated file count as `fill_color`. Returns None when the
+    /// surface pixel lands on the letterbox around the image.
+    fn sample_bilinear(&self, x: u32, y: u32, rgba: &[u8], image_width: usize, decoded: usize) -> Option<[u8; 4]> {
+        let (displayed_x, displayed_y) = self.surface_to_displayed(x, y)?;
+        let (width, height) = self.displayed_size();
+
+        // pixel centers sit at .5, so shift by half a pixel to get the pixels to the top left
+        let (left, top) = ((displayed_x - 0.5).floor(), (displayed_y - 0.5).floor());
+        let (weight_x, weight_y) = (displayed_x - 0.5 - left, displayed_y - 0.5 - top);
+        let tile = self.tile;
+        let neighbour = |value: f32, size: u32| {
+            if tile {
+                value.rem_euclid(size as f32).min(size as f32 - 1.0) as u32
+            } else {
+                value.max(0.0).min(size as f32 - 1.0) as u32
+            }
+        };
+        let (x0, x1) = (neighbour(left, width), neighbour(left + 1.0, width));
+        let (y0, y1) = (neighbour(top, height), neighbour(top + 1.0, height));
+
+        let color_at = |x: u32, y: u32| {
+            let (source_x, source_y) = self.displayed_to_source(x, y);
+            let source = source_y as usize * image_width + source_x as usize;
+            if source < decoded && (source + 1) * 4 <= rgba.len() {
+                [rgba[source * 4], rgba[source * 4 + 1], rgba[source * 4 + 2], rgba[source * 4 + 3]]
+            } else {
+                self.fill_color
+            }
+        };
+        let (top_left, top_right) = (color_at(x0, y0), color_at(x1, y0));
+        let (bottom_left, bottom_right) = (color_at(x0, y1), color_at(x1, y1));
+
+        let mut color = [0; 4];
+        for (channel, value) in color.iter_mut().enumerate() {
+            let top = f32::from(top_left[channel]) * (1.0 - weight_x) + f32::from(top_right[channel]) * weight_x;
+            let bottom = f32::from(bottom_left[channel]) * (1.0 - weight_x) + f32::from(bottom_right[channel]) * weight_x;
+            *value = (top * (1.0 - weight_y) + bottom * weight_y).round() as u8;
+        }
+        Some(color)
+    }
+
+    /// Uses one color for everything behind the image: the letterbox, missing pixels and
+    /// whatever shows through transparent pixels.
+    fn set_background(&mut self, color: [u8; 4]) {
+        self.background = Some(color);
+        self.fill_color = color;
+        self.letterbox_color = color;
+        self.force_redraw();
+    }
+
+    /// Blends a pixel of the image over what is drawn behind it, the background color or
+    /// else a checkerboard, so any transparency shows up. `x` and `y` are the surface
+    /// position of the pixel.
+    fn over_background(&self, rgba: [u8; 4], x: u32, y: u32) -> [u8; 4] {
+        let alpha = u32::from(rgba[3]);
+        if alpha == 255 {
+            return rgba;
+        }
+        let behind = match self.background {
+            Some(color) => color,
+            None => {
+                let checker = if (x / CHECKER_SIZE + y / CHECKER_SIZE).is_multiple_of(2) { CHECKER_LIGHT } else { CHECKER_DARK };
+                [checker, checker, checker, 0xff]
+            }
+        };
+        let mut blended = [0, 0, 0, 0xff];
+        for ((channel, value), behind) in blended.iter_mut().zip(rgba.iter()).zip(behind.iter()).take(3) {
+            *channel = ((u32::from(*value) * alpha + u32::from(*behind) * (255 - alpha) + 127) / 255) as u8;
+        }
+        blended
+    }
+
+    /// The part of the surface the image is drawn on, as left, top, right and bottom edges
+    /// (exclusive), clipped to the surface. Zooming in past the window leaves only the
+    /// visible part of the image.
+    fn visible_rect(&self) -> (u32, u32, u32, u32) {
+        let (image_width, image_height) = self.displayed_size();
+        let scale = self.scale();
+        // the inverse of surface_to_source, for the image edges
+        let left = (self.surface_width as f32 - image_width as f32 * scale) / 2.0 - self.pan_x * scale;
+        let top = (self.surface_height as f32 - image_height as f32 * scale) / 2.0 - self.pan_y * scale;
+        let right = left + image_width as f32 * scale;
+        let bottom = top + image_height as f32 * scale;
+        let clip = |value: f32, limit: u32| value.round().max(0.0).min(limit as f32) as u32;
+        (
+            clip(left, self.surface_width),
+            clip(top, self.surface_height),
+            clip(right, self.surface_width),
+            clip(bottom, self.surface_height),
+        )
+    }
+
+    /// Draws a single pixel crosshair through the center of the visible image and faint,
+    /// dotted lines splitting it in thirds. Every guide pixel inverts what is under it so the
+    /// guides show up on any image. Only the frame is touched, never the decoded values.
+    fn draw_guides(&self, frame: &mut [u8]) {
+        let (left, top, right, bottom) = self.visible_rect();
+        if left >= right || top >= bottom {
+            return;
+        }
+        let surface_width = self.surface_width as usize;
+        let mut invert = |x: u32, y: u32| {
+            let start = (y as usize * surface_width + x as usize) * 4;
+            if let Some(pixel) = frame.get_mut(start..start + 3) {
+                pixel.iter_mut().for_each(|channel| *channel = 255 - *channel);
+            }
+        };
+
+        let (width, height) = (right - left, bottom - top);
+        for third in [width / 3, width * 2 / 3] {
+            (top..bottom).step_by(GUIDE_DOT_SPACING).for_each(|y| invert(left + third, y));
+        }
+        for third in [height / 3, height * 2 / 3] {
+            (left..right).step_by(GUIDE_DOT_SPACING).for_each(|x| invert(x, top + third));
+        }
+        // the crosshair pixel where both lines meet is only inverted once
+        let (center_x, center_y) = (left + width / 2, top + height / 2);
+        (top..bottom).for_each(|y| invert(center_x, y));
+        (left..right).filter(|x| *x != center_x).for_each(|x| invert(x, center_y));
+    }
+
+    /// Converts the decoded pixels of the current frame to RGBA for `draw`, unless that was
+    /// already done. Only a change of frame makes it convert again, redraws for a change of
+    /// the view reuse it.
+    fn update_rgba(&mut self) {
+        let outdated = !matches!(&self.rgba, Some((index, _)) if *index == self.frame_index);
+        if outdated {
+            let rgba = match self.frame() {
+                Some(frame) => {
+                    let mut rgba = frame.to_rgba8();
+                    // pixels missing from a short raster are drawn with `fill_color` instead
+                    rgba.truncate(frame.values.len() * 4);
+                    rgba
+                }
+                None => Vec::new(),
+            };
+            self.rgba = Some((self.frame_index, rgba));
+        }
+    }
+
+    /// The current frame's decoded pixels as RGBA, 4 bytes each.
+    fn rgba(&mut self) -> &[u8] {
+        self.update_rgba();
+        self.rgba.as_ref().map(|(_, rgba)| rgba.as_slice()).unwrap_or_default()
+    }
+
+    /// Per channel histograms of the current frame, 256 bins each: a single one for
+    /// grayscale images, else red, green and blue. They're only computed again when the
+    /// frame changes.
+    fn histogram(&mut self) -> &[[u32; 256]] {
+        let outdated = !matches!(&self.histogram, Some((index, _)) if *index == self.frame_index);
+        if outdated {
+            let mut bins = Vec::new();
+            if let Some(grayscale) = self.frame().map(|frame| frame.header.depth == 1) {
+                bins = vec![[0; 256]; if grayscale { 1 } else { 3 }];
+                for pixel in self.rgba().chunks_exact(4) {
+                    for (channel, value) in bins.iter_mut().zip(pixel) {
+                        channel[*value as usize] += 1;
+                    }
+                }
+            }
+            self.histogram = Some((self.frame_index, bins));
+        }
+        self.histogram.as_ref().map(|(_, bins)| bins.as_slice()).unwrap_or_default()
+    }
+
+    /// Draws the histogram of the current frame as a small graph in the bottom left corner,
+    /// one curve per channel on a darkened backdrop. Every bin is scaled against the
+    /// fullest one.
+    fn draw_histogram(&mut self, frame: &mut [u8]) {
+        let (surface_width, surface_height) = (self.surface_width, self.surface_height);
+        let width = HISTOGRAM_WIDTH.min(surface_width.saturating_sub(2 * HISTOGRAM_MARGIN));
+        let height = HISTOGRAM_HEIGHT.min(surface_height.saturating_sub(2 * HISTOGRAM_MARGIN));
+        if width < 2 || height < 2 {
+            return;
+        }
+        // keep clear of the status line
+        let status_height = if self.show_status { STATUS_HEIGHT } else { 0 };
+        let (left, top) = (HISTOGRAM_MARGIN, surface_height.saturating_sub(HISTOGRAM_MARGIN + height + status_height));
+        let offset = |x: u32, y: u32| ((top + y) as usize * surface_width as usize + (left + x) as usize) * 4;
+
+        for y in 0..height {
+            for x in 0..width {
+                let start = offset(x, y);
+                if let Some(pixel) = frame.get_mut(start..start + 3) {
+                    pixel.iter_mut().for_each(|channel| *channel /= 3);
+                }
+            }
+        }
+
+        let histogram = self.histogram().to_vec();
+        let tallest = histogram.iter().flat_map(|bins| bins.iter()).copied().max().unwrap_or(0).max(1);
+        let colors: &[[u8; 3]] = if histogram.len() == 1 {
+            &[[0xff, 0xff, 0xff]]
+        } else {
+            &[[0xff, 0x40, 0x40], [0x40, 0xff, 0x40], [0x40, 0x80, 0xff]]
+        };
+        for (bins, color) in histogram.iter().zip(colors) {
+            // the y of the curve in every column, 0 being the top of the graph
+            let curve_y = |x: u32| {
+                let count = bins[(x * 256 / width) as usize];
+                height - 1 - (u64::from(count) * u64::from(height - 1) / u64::from(tallest)) as u32
+            };
+            for x in 0..width {
+                // join each point to the previous one so steep slopes don't leave gaps
+                let (y, previous) = (curve_y(x), curve_y(x.saturating_sub(1)));
+                for y in y.min(previous)..=y.max(previous) {
+                    let start = offset(x, y);
+                    if let Some(pixel) = frame.get_mut(start..start + 3) {
+                        pixel.copy_from_slice(color);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Draws a line of text along the bottom of the window with the type and size of the
+    /// current frame and how many of its pixels were decoded, e.g. "P6 640x480  decoded
+    /// 30012 / 307200 pixels", so a truncated file stands out right away.
+    fn draw_status(&mut self, frame: &mut [u8]) {
+        let decoded = self.rgba().len() / 4;
+        let image = match self.frame() {
+            Some(image) => image,
+            None => return,
+        };
+        let header = &image.header;
+        let text = format!(
+            "{} {}x{}  decoded {} / {} pixels",
+            header.ppm_type, header.width, header.height, decoded, header.pixel_count().unwrap_or(0)
+        );
+
+        let (surface_width, surface_height) = (self.surface_width as usize, self.surface_height as usize);
+        let top = surface_height.saturating_sub(STATUS_HEIGHT as usize);
+        for pixel in frame.chunks_exact_mut(4).skip(top * surface_width) {
+            pixel[..3].iter_mut().for_each(|channel| *channel /= 3);
+        }
+        let (left, top) = (STATUS_PADDING as usize, top + STATUS_PADDING as usize);
+        font::draw_text(frame, surface_width, left, top, &text, [0xff, 0xff, 0xff, 0xff]);
+    }
+
+    /// Draw the `World` state to the frame buffer.
+    ///
+    /// The frame is RGBA with 8 bits per channel, `wgpu::TextureFormat::Rgba8UnormSrgb` or
+    /// with --colorspace linear `wgpu::TextureFormat::Rgba8Unorm`. The values are written
+    /// the same either way, only how the GPU interprets them differs.
+    fn draw(&mut self, frame: &mut [u8]) {
+        if self.single_draw && self.has_been_drawn {
+            return
+        }
+        self.update_rgba();
+        let rgba = self.rgba.as_ref().map(|(_, rgba)| rgba.as_slice()).unwrap_or_default();
+        let frame_instance = self.frame().unwrap();
+        let surface_width = self.surface_width as usize;
+        if frame_instance.header.ppm_type != PpmType::P0 && surface_width > 0 {
+            let image_width = frame_instance.header.width.max(0) as usize;
+            // a truncated file decodes to fewer values than the surface has pixels
+            let decoded = rgba.len() / 4;
+            for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
+                let x = (i % surface_width) as u32;
+                let y = (i / surface_width) as u32;
+                if self.filter == Filter::Bilinear {
+                    match self.sample_bilinear(x, y, rgba, image_width, decoded) {
+                        Some(color) => pixel.copy_from_slice(&self.over_background(self.adjust_color(&color), x, y)),
+                        None => pixel.copy_from_slice(&self.letterbox_color),
+                    }
+                    continue;
+                }
+                match self.surface_to_source(x, y) {
+                    Some((source_x, source_y)) => {
+                        let source = source_y as usize * image_width + source_x as usize;
+                        if source < decoded && (source + 1) * 4 <= rgba.len() {
+                            let color = self.adjust_color(&rgba[source * 4..(source + 1) * 4]);
+                            pixel.copy_from_slice(&self.over_background(color, x, y));
+                        } else {
+                            pixel.copy_from_slice(&self.fill_color);
+                        }
+                    }
+                    None => pixel.copy_from_slice(&self.letterbox_color),
+                }
+            }
+            if self.guides {
+                self.draw_guides(frame);
+            }
+            if self.show_histogram {
+                self.draw_histogram(frame);
+            }
+            if self.show_status {
+                self.draw_status(frame);
+            }
+        } 
+
+        if self.single_draw && !self.has_been_drawn {
+            self.has_been_drawn = true;
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A world showing `image` 1:1 on a surface of the same size.
+    fn world_showing(image: Image) -> World {
+        let mut world = World::new();
+        world.surface_width = image.header.width as u32;
+        world.surface_height = image.header.height as u32;
+        world.set_frames(vec![image]);
+        world
+    }
+
+    /// Draws `world` into a new frame, one RGBA array per surface pixel.
+    fn draw(world: &mut World) -> Vec<[u8; 4]> {
+        let mut frame = vec![0; (world.surface_width * world.surface_height * 4) as usize];
+        world.draw(&mut frame);
+        frame.chunks_exact(4).map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]]).collect()
+    }
+
+    #[test]
+    fn missing_pixels_are_drawn_in_the_fill_color() {
+        // the raster stops after three of the four pixels
+        let image = decode(&b"P6\n2 2\n255\n\x10\x20\x30\x40\x50\x60\x70\x80\x90"[..]).unwrap();
+        assert_eq!(image.values.len(), 3);
+        let mut world = world_showing(image);
+        assert_eq!(draw(&mut world), [[0x10, 0x20, 0x30, 0xff], [0x40, 0x50, 0x60, 0xff], [0x70, 0x80, 0x90, 0xff], [0, 0, 0, 0xff]]);
+    }
+
+    #[test]
+    fn images_shown_from_rgba_draw_like_decoded_ones() {
+        let image = decode(&b"P6\n2 2\n255\n\x10\x20\x30\x40\x50\x60\x70\x80\x90"[..]).unwrap();
+        let mut header_only = Image::new();
+        header_only.header = image.header.clone();
+        let rgba = image.to_rgba8()[..12].to_vec();
+        let mut decoded = world_showing(image);
+        let mut from_rgba = world_showing(header_only.clone());
+        from_rgba.show(vec![header_only], Some(rgba));
+        assert_eq!(draw(&mut from_rgba), draw(&mut decoded));
+        assert_eq!(from_rgba.histogram(), decoded.histogram());
+    }
+
+    #[test]
+    fn rotated_pixels_map_back_to_the_source() {
+        // a 3x2 image, so 90 and 270 degrees display it 2 wide and 3 high
+        let (width, height) = (3, 2);
+        assert_eq!(rotate_to_source(0, 0, 0, width, height), (0, 0));
+        assert_eq!(rotate_to_source(2, 1, 0, width, height), (2, 1));
+        // turned clockwise, the bottom left corner ends up at the top left
+        assert_eq!(rotate_to_source(0, 0, 90, width, height), (0, 1));
+        assert_eq!(rotate_to_source(1, 0, 90, width, height), (0, 0));
+        assert_eq!(rotate_to_source(1, 2, 90, width, height), (2, 0));
+        assert_eq!(rotate_to_source(0, 0, 180, width, height), (2, 1));
+        assert_eq!(rotate_to_source(2, 1, 180, width, height), (0, 0));
+        assert_eq!(rotate_to_source(0, 0, 270, width, height), (2, 0));
+        assert_eq!(rotate_to_source(1, 2, 270, width, height), (0, 1));
+    }
+
+    #[test]
+    fn flipping_both_ways_is_a_half_turn() {
+        let image = decode(&b"P5\n3 2\n255\n\x00\x01\x02\x03\x04\x05"[..]).unwrap();
+        let mut flipped = world_showing(image.clone());
+        flipped.flip_horizontal = true;
+        flipped.flip_vertical = true;
+        let mut rotated = world_showing(image);
+        rotated.rotation = 180;
+        for (x, y) in (0..2).flat_map(|y| (0..3).map(move |x| (x, y))) {
+            assert_eq!(flipped.displayed_to_source(x, y), rotated.displayed_to_source(x, y));
+        }
+    }
+
+    #[test]
+    fn luminance_uses_rec_709_weights() {
+        assert_eq!(luminance(255, 0, 0), 54);
+        assert_eq!(luminance(0, 255, 0), 182);
+        assert_eq!(luminance(0, 0, 255), 18);
+        assert_eq!(luminance(255, 255, 255), 255);
+    }
+
+    #[test]
+    fn bilinear_upscaling_weights() {
+        let image = decode(&b"P5\n2 2\n255\n\x00\xc8\x64\x28"[..]).unwrap();
+        let mut world = world_showing(image);
+        world.filter = Filter::Bilinear;
+        world.surface_width = 4;
+        world.surface_height = 4;
+        // every surface pixel center lands a quarter or three quarters of the way between two
+        // image pixel centers, or past the outermost ones where the edge is clamped
+        let gray: Vec<u8> = draw(&mut world).iter().map(|pixel| pixel[0]).collect();
+        assert_eq!(gray, [
+            0, 50, 150, 200,
+            25, 59, 126, 160,
+            75, 76, 79, 80,
+            100, 85, 55, 40,
+        ]);
+    }
+
+    #[test]
+    fn missing_pixels_use_the_missing_color() {
+        let image = decode(&b"P5\n2 1\n255\n\x40"[..]).unwrap();
+        let mut world = world_showing(image);
+        world.fill_color = parse_hex_color("ff00ff").unwrap();
+        assert_eq!(draw(&mut world), [[0x40, 0x40, 0x40, 0xff], [0xff, 0x00, 0xff, 0xff]]);
+        world.filter = Filter::Bilinear;
+        world.force_redraw();
+        assert_eq!(draw(&mut world), [[0x40, 0x40, 0x40, 0xff], [0xff, 0x00, 0xff, 0xff]]);
+    }
+}
@@ -0,0 +1,139 @@
+// Decoding straight out of a memory mapped file. The binary decoders normally read the
+// whole raster into memory before turning it into pixels, which for an image of several
+// hundred megabytes doubles the memory needed. Here the raster is read from the mapping
+// instead: `decode_file` only allocates the decoded pixels, and `decode_rgba8` goes one
+// step further and only allocates the RGBA framebuffer a viewer draws from, which is what
+// the viewer opens single binary images with.
+
+use crate::{for_each_raster_pixel, is_header_whitespace, pixels_from_raster, read_ppm_ascii_file, read_ppm_header, starts_image, warn_trailing, Image, ImageHeader, PixelData, PpmError, Strictness};
+use memmap2::Mmap;
+use std::fs::File;
+use std::path::Path;
+
+/// Decodes every image in the file at `path`, like `decode_all`, but reads binary rasters
+/// directly from a memory map of the file. ASCII images are parsed as usual.
+pub fn decode_file(path: &Path) -> Result<Vec<Image>, PpmError> {
+    let map = map_file(path)?;
+
+    // a compressed file has to be decompressed as a stream anyway
+    #[cfg(feature = "gzip")]
+    {
+        if map.starts_with(&crate::GZIP_MAGIC) {
+            return crate::decode_all(&map[..]);
+        }
+    }
+
+    let mut rest: &[u8] = &map;
+    let mut images = Vec::new();
+    loop {
+        let (_, header) = read_ppm_header(&mut rest, Strictness::Normal)?;
+        let mut image = Image::new();
+        match header.raster_size() {
+            Some(size) => {
+                let size = size.min(rest.len());
+                image.values = pixels_from_raster(&rest[..size], &header);
+                rest = &rest[size..];
+            }
+            None => {
+                image.values = PixelData::for_header(&header);
+                let size = read_ppm_ascii_file(&mut &rest[..], &header, Strictness::Normal, &mut image.values)?;
+                rest = &rest[size..];
+            }
+        }
+        image.header = header;
+        images.push(image);
+
+        // tolerate whitespace between images, then stop once there's nothing left
+        let whitespace = rest.iter().take_while(|byte| is_header_whitespace(**byte)).count();
+        rest = &rest[whitespace..];
+        if rest.is_empty() {
+            break;
+        }
+        if !starts_image(rest) {
+            warn_trailing(rest.len() as u64);
+            break;
+        }
+    }
+    Ok(images)
+}
+
+/// Decodes the file at `path` into a framebuffer of RGBA bytes, laid out like
+/// `Image::to_rgba8` lays it out but holding only the pixels in the raster, so a short
+/// raster gives a shorter buffer. The pixels are converted right out of the memory map,
+/// without an `Image` holding them in between. Returns None for anything but a single
+/// binary image, such as ASCII, compressed or animated files, which are left to
+/// `decode_file`.
+pub fn decode_rgba8(path: &Path) -> Result<Option<(ImageHeader, Vec<u8>)>, PpmError> {
+    let map = map_file(path)?;
+    #[cfg(feature = "gzip")]
+    {
+        if map.starts_with(&crate::GZIP_MAGIC) {
+            return Ok(None);
+        }
+    }
+
+    let mut rest: &[u8] = &map;
+    let (_, header) = read_ppm_header(&mut rest, Strictness::Normal)?;
+    let size = match header.raster_size() {
+        Some(size) => size.min(rest.len()),
+        None => return Ok(None),
+    };
+    let (raster, after) = rest.split_at(size);
+    let whitespace = after.iter().take_while(|byte| is_header_whitespace(**byte)).count();
+    let after = &after[whitespace..];
+    if starts_image(after) {
+        return Ok(None);
+    }
+    if !after.is_empty() {
+        warn_trailing(after.len() as u64);
+    }
+
+    let mut rgba = Vec::with_capacity(header.pixel_capacity() * 4);
+    for_each_raster_pixel(raster, &header, |pixel| rgba.extend_from_slice(&<[u8; 4]>::from(pixel)));
+    Ok(Some((header, rgba)))
+}
+
+/// Maps the whole file at `path` into memory.
+fn map_file(path: &Path) -> Result<Mmap, PpmError> {
+    let file = File::open(path)?;
+    // safety: the mapping is only read while decoding. If another process truncates the
+    // file meanwhile this can fault, which is the usual caveat of memory mapped files.
+    #[allow(unsafe_code)]
+    let map = unsafe { Mmap::map(&file)? };
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `contents` to a file of its own in the temp directory and returns its path.
+    fn temp_file(name: &str, contents: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("ppmviewer-mmap-{}-{}.ppm", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn rgba8_matches_the_decoded_image() {
+        // the raster stops one pixel short, which is left out
+        let path = temp_file("short", b"P6\n2 2\n255\n\x01\x02\x03\x04\x05\x06\x07\x08\x09");
+        let decoded = decode_rgba8(&path);
+        let image = crate::decode(File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let (header, rgba) = decoded.unwrap().unwrap();
+        assert_eq!((header.width, header.height), (2, 2));
+        assert_eq!(rgba, image.to_rgba8()[..12]);
+    }
+
+    #[test]
+    fn rgba8_leaves_ascii_and_animations_to_decode_file() {
+        for (name, contents) in [("ascii", &b"P3\n1 1\n255\n1 2 3\n"[..]), ("frames", b"P5\n1 1\n255\n\x01\nP5\n1 1\n255\n\x02")] {
+            let path = temp_file(name, contents);
+            let decoded = decode_rgba8(&path);
+            std::fs::remove_file(&path).unwrap();
+            assert!(decoded.unwrap().is_none());
+        }
+    }
+}